@@ -1,4 +1,7 @@
 use std::fmt;
+use std::io;
+use std::net::{Ipv6Addr, ToSocketAddrs};
+use std::str::FromStr;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AddrPort {
@@ -13,10 +16,123 @@ impl AddrPort {
             port,
         }
     }
+
+    /// Resolves the address to a numeric one, of the IPv6 family if `ipv6` and the address
+    /// has one, otherwise of the IPv4 family if it has one
+    pub fn resolve(&self, ipv6: bool) -> Result<AddrPort, io::Error> {
+        let addresses: Vec<_> = (self.address.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|e| io::Error::new(e.kind(), format!("cannot resolve {}: {}", self, e)))?
+            .collect();
+
+        let address = addresses
+            .iter()
+            .find(|address| address.is_ipv6() == ipv6)
+            .or_else(|| addresses.first())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} does not resolve to any address", self),
+                )
+            })?;
+
+        Ok(AddrPort::new(address.ip().to_string(), self.port))
+    }
+}
+
+impl FromStr for AddrPort {
+    type Err = String;
+
+    /// Parses `address:port`, the port being everything after the last colon. IPv6 addresses
+    /// are bracketed, e.g. `[fd00::1]:51820`, and the port cannot be 0.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, port) = s
+            .rsplit_once(':')
+            .ok_or_else(|| format!("{} is not in the address:port form", s))?;
+
+        let address = match address.strip_prefix('[') {
+            Some(bracketed) => {
+                let address = bracketed
+                    .strip_suffix(']')
+                    .ok_or_else(|| format!("{} lacks a closing bracket", s))?;
+
+                address
+                    .parse::<Ipv6Addr>()
+                    .map_err(|e| format!("invalid IPv6 address {}: {}", address, e))?;
+
+                address
+            }
+            None if address.contains(':') => {
+                return Err(format!(
+                    "IPv6 addresses must be bracketed, e.g. [{}]:{}",
+                    address, port
+                ))
+            }
+            None => address,
+        };
+
+        if address.is_empty() {
+            return Err(format!("{} has no address", s));
+        }
+
+        let port = port
+            .parse()
+            .map_err(|e| format!("invalid port {}: {}", port, e))?;
+
+        if port == 0 {
+            return Err("the port must be within 1..=65535".to_string());
+        }
+
+        Ok(AddrPort::new(address, port))
+    }
 }
 
 impl fmt::Display for AddrPort {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "{}:{}", self.address, self.port)
+        // IPv6 literals are bracketed to tell their colons from the port's
+        match self.address.parse::<Ipv6Addr>() {
+            Ok(_) => write!(f, "[{}]:{}", self.address, self.port),
+            Err(_) => write!(f, "{}:{}", self.address, self.port),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> (String, u16) {
+        let addrport: AddrPort = s.parse().unwrap();
+
+        (addrport.address, addrport.port)
+    }
+
+    #[test]
+    fn names_and_addresses_are_parsed() {
+        assert_eq!(parse("vpn.com:31337"), ("vpn.com".to_string(), 31337));
+        assert_eq!(parse("1.2.3.4:51820"), ("1.2.3.4".to_string(), 51820));
+        assert_eq!(parse("[fd00::1]:51820"), ("fd00::1".to_string(), 51820));
+    }
+
+    #[test]
+    fn parsed_addresses_are_printed_back() {
+        for s in ["vpn.com:31337", "1.2.3.4:51820", "[fd00::1]:51820"] {
+            assert_eq!(s.parse::<AddrPort>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn malformed_addresses_are_rejected() {
+        for s in [
+            "vpn.com:0",
+            "vpn.com:65536",
+            "vpn.com",
+            ":51820",
+            "fd00::1:51820",
+            "[fd00::1:51820",
+            "[vpn.com]:51820",
+        ] {
+            assert!(s.parse::<AddrPort>().is_err(), "{}", s);
+        }
     }
 }
@@ -1,14 +1,26 @@
+use chrono::{DateTime, Duration, SubsecRound, Utc};
+use clap::Shell;
 use ipnet::IpNet;
 use std::net::IpAddr;
 use std::path::PathBuf;
+use std::str::FromStr;
 use structopt::StructOpt;
+use wireguard_configure::compat::WgVersion;
+use wireguard_configure::configuration::ConfigFormat;
+use wireguard_configure::validation;
+use wireguard_configure::{AddrPort, TableType};
 
 #[derive(StructOpt)]
 #[structopt(author)]
 pub struct Arguments {
     #[structopt(subcommand)]
     pub subcommand: SubCommand,
-    /// Configuration file to use
+    /// Name of a configuration in /etc/wireguard (WireGuard\Data\Configurations in the
+    /// program files on Windows), or in the directory set by WG_CONFIGURE_DIR, e.g: wg0 ->
+    /// /etc/wireguard/wg0.toml
+    #[structopt(name = "configuration-name")]
+    pub name: Option<String>,
+    /// Configuration file to use, - for stdin
     #[structopt(
         name = "configuration-file",
         parse(from_os_str),
@@ -16,45 +28,672 @@ pub struct Arguments {
         overrides_with = "configuration-name"
     )]
     pub config: Option<PathBuf>,
+    /// Format of the configuration, also used to write it back: yaml, toml or json. It is
+    /// taken from the file extension otherwise, and guessed between TOML and YAML for stdin.
+    #[structopt(long, global = true, possible_values = &["yaml", "toml", "json"])]
+    pub format: Option<ConfigFormat>,
+    /// Print private keys in the configuration previewed by --dry-run, they are redacted
+    /// otherwise. Piped configurations are always printed with their private keys.
+    #[structopt(long, global = true)]
+    pub reveal_secrets: bool,
+    /// Print the changed configuration to stdout instead of writing it, as when it is piped
+    #[structopt(long, global = true)]
+    pub dry_run: bool,
+    /// Start the printed .conf files with the tool version, the date and the configuration name
+    #[structopt(long, global = true)]
+    pub verbose_comments: bool,
+    /// The wg binary to run, instead of the one set by WG_BINARY or found in PATH
+    #[structopt(long, global = true, parse(from_os_str))]
+    pub wg_path: Option<PathBuf>,
 }
 
+// parsed once, the size of the variants does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(StructOpt)]
 pub enum SubCommand {
+    /// Create a configuration with a new router, written to <configuration-file> or to the
+    /// configuration directory
+    Init {
+        /// Name of the configuration and of its router
+        name: String,
+        /// Router subnet, e.g. 10.0.1.0/24 for the router to be 10.0.1.1, or 10.0.1.254/24
+        internal_subnet: IpNet,
+        /// Public address and port of the router, e.g. vpn.com:31337
+        endpoint: AddrPort,
+        /// Warn if the endpoint host name does not resolve
+        #[structopt(long)]
+        verify_endpoint: bool,
+    },
     /// Generate an example configuration file
-    GenerateExample,
+    GenerateExample {
+        /// Use an IPv6 router subnet (fd00::/64) instead of 10.0.1.0/24
+        #[structopt(long)]
+        ipv6: bool,
+    },
+    /// Print the completion script of a shell (bash, zsh, fish, powershell or elvish), e.g.
+    /// to be redirected to ~/.local/share/bash-completion/completions/wireguard-configure
+    Completions {
+        #[structopt(possible_values = &Shell::variants(), case_insensitive = true)]
+        shell: Shell,
+    },
+    /// Convert an existing wg-quick router configuration, printing it to stdout
+    Import {
+        /// The wg-quick configuration file to import
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+        /// The router's public address, which wg-quick configurations do not include
+        #[structopt(long)]
+        external_address: Option<String>,
+        /// The subnets routed through the VPN by the clients, which a router configuration
+        /// does not include. The router subnets by default.
+        #[structopt(short = "a")]
+        allowed_ips: Vec<IpNet>,
+    },
     /// List clients in this configuration
-    List,
+    List {
+        /// Output format
+        #[structopt(long, default_value = "table", possible_values = &["table", "json"])]
+        output_format: ListFormat,
+        /// Only list the clients carrying this tag
+        #[structopt(long)]
+        tag: Option<String>,
+        /// Sort the clients, the router staying first. Defaults to the configuration order.
+        #[structopt(long, possible_values = &["name", "address", "allowed"])]
+        sort: Option<ListSort>,
+        /// Do not color the table, as when NO_COLOR is set or the output is not a terminal
+        #[structopt(long)]
+        no_color: bool,
+        /// Add the public key, DNS and MTU columns to the table
+        #[structopt(long)]
+        wide: bool,
+        /// Show whole public keys rather than their first 8 characters
+        #[structopt(long, requires = "wide")]
+        full_keys: bool,
+    },
+    /// Print how many addresses of the router subnet are used, and the next free one
+    Pool,
     /// Add a client to the configuration
-    AddClient {
-        /// Name of client to add
+    AddClient(AddClientArgs),
+    /// Show every setting of a client
+    Show {
+        /// Name of the client to show
         client_name: String,
-        /// Internal address for the new client
-        #[structopt(short = "i")]
-        internal_address: IpAddr,
-        /// A list of subnets to be routed through the VPN for this client (e.g 10.0.0.1/32)
-        #[structopt(required = true, short = "a")]
-        allowed_ips: Vec<IpNet>,
-        /// The DNS server to use
-        #[structopt(short, long)]
-        dns: Option<IpAddr>,
-        /// Persistent keepalive for the client
-        #[structopt(short, long)]
-        persistent_keepalive: Option<usize>,
-        /// Use the given public key, do not use an auto-generated key-pair
-        #[structopt(long = "pub")]
-        public_key: Option<String>,
+        /// Print the private key instead of masking it
+        #[structopt(long)]
+        reveal: bool,
+    },
+    /// Write the whole configuration, private keys included, to a file (mode 0600)
+    Export {
+        /// The file to write
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Write every client configuration to <dir>/<client_name>.conf (mode 0600)
+    ExportAll {
+        /// Directory to write the configurations to, created if missing
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+    },
+    /// Print an Ansible dynamic inventory (JSON) of the router and the clients, grouped by
+    /// role and by tag
+    Inventory {
+        /// Accepted for Ansible, which calls inventory scripts with --list
+        #[structopt(long = "list")]
+        _list: bool,
+    },
+    /// Compare the configuration with the peers of the running interface
+    Diff {
+        /// Interface to compare with, defaults to the configuration name
+        #[structopt(long)]
+        interface: Option<String>,
+    },
+    /// Print the latest handshake and the traffic of each peer of the running interface
+    Stats {
+        /// Interface to read, defaults to the configuration name
+        #[structopt(long)]
+        interface: Option<String>,
+    },
+    /// Write a full-mesh configuration for the router and every client to <dir>/<name>.conf
+    /// (mode 0600), each node reaching every other one directly
+    Mesh {
+        /// Directory to write the configurations to, created if missing
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+    },
+    /// Check the configuration and the environment, exiting with 1 on warnings and 4 on errors
+    Check,
+    /// Check that the public key of the router and of every client with a private key is
+    /// derived from that private key, exiting with 4 on mismatches
+    VerifyKeys,
+    /// Reserve an address or a network so that it is never assigned automatically, or list
+    /// the reservations
+    Reserve {
+        /// Address or network to reserve, e.g. 10.0.1.254 or 10.0.1.128/28
+        #[structopt(parse(try_from_str = parse_network))]
+        cidr: Option<IpNet>,
+        /// Release the reservation instead
+        #[structopt(long, requires = "cidr")]
+        remove: bool,
     },
     /// Remove a client from the configuration
     RemoveClient {
         /// Name of client to remove
-        #[structopt(required = true)]
+        #[structopt(
+            required_unless_one = &["by-address", "by-pubkey"],
+            conflicts_with_all = &["by-address", "by-pubkey"]
+        )]
+        client_name: Option<String>,
+        /// Select the client by internal address instead of name
+        #[structopt(long, conflicts_with = "by-pubkey")]
+        by_address: Option<IpAddr>,
+        /// Select the client by public key instead of name
+        #[structopt(long)]
+        by_pubkey: Option<String>,
+        /// Also remove the client from the running interface named after the configuration
+        #[structopt(long)]
+        apply: bool,
+        /// Append the removal, with its date and the client's public key, as a JSON line to
+        /// this audit file
+        #[structopt(long, parse(from_os_str))]
+        record: Option<PathBuf>,
+    },
+    /// Remove every expired client at once, only printing the clients which would be removed
+    /// with --dry-run
+    Prune {
+        /// Also remove the disabled clients
+        #[structopt(long)]
+        include_disabled: bool,
+    },
+    /// Move a client, keys included, to another configuration, giving it a free address there
+    /// if its own is taken
+    MoveClient {
+        /// Name of the client to move
         client_name: String,
+        /// Destination configuration: a file, or else a name resolved as for
+        /// <configuration-name>
+        #[structopt(parse(from_os_str))]
+        to: PathBuf,
     },
+    /// Generate a new keypair for a client, keeping all its other settings
+    RotateKeys {
+        /// Name of the client whose keys are rotated
+        client_name: String,
+    },
+    /// Generate a new keypair for the router, after which every client needs its
+    /// configuration again
+    #[structopt(alias = "regenerate-router-keys")]
+    RotateRouterKeys {
+        /// Confirm that the clients lose access until they get their new configuration
+        #[structopt(long)]
+        yes: bool,
+    },
+    /// Change the router configuration, only the given fields are modified
+    SetRouter(SetRouterArgs),
+    /// Change the router's public address, which client configurations use as endpoint, or
+    /// its alternate endpoints picked with `client-config --endpoint-index`
+    SetEndpoint {
+        /// Public address and port of the router (e.g. vpn.com:31337 or [fd00::1]:51820), the
+        /// port is also the one it listens on
+        #[structopt(required_unless_one = &["alternates", "clear_alternates"])]
+        endpoint: Option<AddrPort>,
+        /// Add an alternate endpoint, e.g. a backup address or port, after the existing ones.
+        /// Can be given several times.
+        #[structopt(long = "alternate", number_of_values = 1)]
+        alternates: Vec<AddrPort>,
+        /// Remove the alternate endpoints, before adding those given with --alternate
+        #[structopt(long)]
+        clear_alternates: bool,
+        /// Warn if an endpoint host name does not resolve
+        #[structopt(long)]
+        verify_endpoint: bool,
+    },
+    /// Write the router configuration to /etc/wireguard and bring the interface up
+    Up,
+    /// Bring the interface down
+    Down,
+    /// Write the router configuration to /etc/wireguard and restart the interface
+    Reload,
     /// Print the router configuration
-    RouterConfig,
+    RouterConfig {
+        /// Only include the clients carrying this tag
+        #[structopt(long)]
+        tag: Option<String>,
+        /// Warn about directives unsupported by this wireguard-tools version (e.g. 1.0)
+        #[structopt(long)]
+        target_version: Option<WgVersion>,
+        /// Write the configuration to this file (mode 0600) instead of stdout, - for stdout
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
     /// Print the client configuration
     ClientConfig {
         /// Name of the client's configuration to print
         client_name: String,
+        /// Router endpoint baked into the configuration: 0 is the external address, 1 and
+        /// above the alternate endpoints in order. Defaults to the client's endpoint override,
+        /// if any, or to the external address.
+        #[structopt(long)]
+        endpoint_index: Option<usize>,
+        /// Write the endpoint as the address it resolves to, of the client's address family if
+        /// possible, for networks with broken DNS
+        #[structopt(long)]
+        resolve: bool,
+        /// Warn about directives unsupported by this wireguard-tools version (e.g. 1.0)
+        #[structopt(long)]
+        target_version: Option<WgVersion>,
+        /// Write the configuration to this file (mode 0600) instead of stdout, - for stdout
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
     },
+    /// Encrypt the client configuration for an age recipient or SSH public key
+    ExportEncrypted {
+        /// Name of the client's configuration to export
+        client_name: String,
+        /// The age recipient (age1...) or SSH public key to encrypt to
+        recipient: String,
+        /// Output file, defaults to <client_name>.conf.age
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, StructOpt)]
+pub struct AddClientArgs {
+    /// Name of client to add
+    #[structopt(required_unless = "count")]
+    pub client_name: Option<String>,
+    /// Add this many clients at once, named after --name-prefix and numbered from 1, with
+    /// the next free addresses
+    #[structopt(
+        long,
+        requires = "name-prefix",
+        conflicts_with_all = &[
+            "client-name",
+            "internal-address",
+            "internal-address-v6",
+            "public-key",
+            "private-key",
+            "private-key-file",
+            "listen-port",
+            "mesh-endpoint",
+        ]
+    )]
+    pub count: Option<usize>,
+    /// Name of the clients added with --count, followed by their number, e.g. client-
+    #[structopt(long, requires = "count")]
+    pub name_prefix: Option<String>,
+    /// Internal address for the new client, either bare or as a /32 (/128 for IPv6) network
+    #[structopt(short = "i", parse(try_from_str = parse_host_address))]
+    pub internal_address: Option<IpAddr>,
+    /// IPv6 address for the new client of a dual-stack router, the next free one if omitted
+    #[structopt(long, parse(try_from_str = parse_host_address_v6))]
+    pub internal_address_v6: Option<IpAddr>,
+    /// Free-form description, written as a comment above the client's sections
+    #[structopt(long)]
+    pub description: Option<String>,
+    /// Tag used to group clients, e.g. by team or location, can be repeated
+    #[structopt(long = "tag")]
+    pub tags: Vec<String>,
+    /// When the client's access ends, an RFC 3339 date (e.g. 2024-05-01T12:00:00Z) or a
+    /// duration from now in minutes, hours, days or weeks (e.g. 30d)
+    #[structopt(long, parse(try_from_str = parse_expiry))]
+    pub expires: Option<DateTime<Utc>>,
+    /// A list of subnets to be routed through the VPN for this client (e.g 10.0.0.1/32)
+    #[structopt(short = "a", conflicts_with_all = &["full-tunnel", "split-tunnel"])]
+    #[cfg_attr(
+        not(feature = "interactive"),
+        structopt(required_unless_one = &["like", "full-tunnel", "split-tunnel"])
+    )]
+    pub allowed_ips: Vec<IpNet>,
+    /// Also drop the allowed IPs within another one, e.g. 10.0.1.5/32 along with 10.0.1.0/24.
+    /// Repeated allowed IPs are always dropped.
+    #[structopt(long)]
+    pub merge: bool,
+    /// A network behind the client, e.g. the LAN of a site-to-site gateway, which the router
+    /// routes to it. Can be repeated.
+    #[structopt(long = "route-subnet")]
+    pub routed_subnets: Vec<IpNet>,
+    /// Route all traffic through the VPN (0.0.0.0/0 and ::/0). Cannot be combined with -a,
+    /// takes precedence over the allowed IPs copied by --like.
+    #[structopt(long, conflicts_with = "split-tunnel")]
+    pub full_tunnel: bool,
+    /// A network kept out of the full tunnel, e.g. the local LAN 192.168.1.0/24, can be
+    /// repeated. The allowed IPs then cover everything else.
+    #[structopt(long, requires = "full-tunnel")]
+    pub exclude: Vec<IpNet>,
+    /// Only route the router subnet through the VPN. Cannot be combined with -a, takes
+    /// precedence over the allowed IPs copied by --like.
+    #[structopt(long)]
+    pub split_tunnel: bool,
+    /// The DNS servers to use, can be repeated
+    #[structopt(short, long)]
+    pub dns: Vec<IpAddr>,
+    /// A DNS search domain, used along with the DNS servers, can be repeated
+    #[structopt(long, parse(try_from_str = parse_domain))]
+    pub dns_search: Vec<String>,
+    /// Persistent keepalive for the client in seconds, up to 3600, usually 25 for clients
+    /// behind NAT. 0 disables it explicitly, e.g. instead of the one copied by --like.
+    #[structopt(short, long, parse(try_from_str = parse_keepalive))]
+    pub persistent_keepalive: Option<usize>,
+    /// The client is behind NAT, e.g. a roaming laptop: it gets a persistent keepalive of 25
+    /// seconds unless one is set
+    #[structopt(long)]
+    pub behind_nat: bool,
+    /// MTU of the client interface, within 576..=1500
+    #[structopt(long)]
+    pub mtu: Option<u16>,
+    /// Allow an MTU up to 9000, for jumbo frames
+    #[structopt(long, requires = "mtu")]
+    pub jumbo: bool,
+    /// Routing table for the routes of the client interface: off, auto or a table number
+    #[structopt(long, parse(try_from_str = parse_table))]
+    pub table: Option<TableType>,
+    /// Fixed port the client listens on, e.g. for always-on relays
+    #[structopt(long)]
+    pub listen_port: Option<u16>,
+    /// Router endpoint (address:port) for this client instead of the external address,
+    /// e.g. the router's LAN address for local clients
+    #[structopt(long)]
+    pub endpoint: Option<AddrPort>,
+    /// Address and port the other nodes of a mesh reach this client at, its listen port
+    /// defaulting to that port
+    #[structopt(long)]
+    pub mesh_endpoint: Option<AddrPort>,
+    /// Command run by wg-quick before bringing the client interface up
+    #[structopt(long, parse(try_from_str = parse_hook))]
+    pub preup: Option<String>,
+    /// Command run by wg-quick after bringing the client interface up
+    #[structopt(long, parse(try_from_str = parse_hook))]
+    pub postup: Option<String>,
+    /// Command run by wg-quick before bringing the client interface down
+    #[structopt(long, parse(try_from_str = parse_hook))]
+    pub predown: Option<String>,
+    /// Command run by wg-quick after bringing the client interface down
+    #[structopt(long, parse(try_from_str = parse_hook))]
+    pub postdown: Option<String>,
+    /// Use the given public key, do not use an auto-generated key-pair
+    #[structopt(long = "pub")]
+    pub public_key: Option<String>,
+    /// Read the private key from this file whenever a configuration is rendered, instead of
+    /// storing it in the configuration
+    #[structopt(long, parse(from_os_str), conflicts_with = "public-key")]
+    pub private_key_file: Option<PathBuf>,
+    /// Use the given private key, deriving its public key, instead of generating a key-pair
+    #[structopt(long, conflicts_with_all = &["public-key", "private-key-file"])]
+    pub private_key: Option<String>,
+    /// Copy allowed IPs, DNS servers and search domains and keepalive from an existing client, unless given explicitly
+    #[structopt(long)]
+    pub like: Option<String>,
+    /// Refuse the router's own address and warn about network/broadcast-style addresses
+    #[structopt(long)]
+    pub check_subnet_alignment: bool,
+    /// Accept an internal address outside of the router subnet, e.g. for split setups
+    #[structopt(long)]
+    pub force: bool,
+    /// Also add the client to the running interface named after the configuration
+    #[structopt(long)]
+    pub apply: bool,
+}
+
+#[derive(StructOpt)]
+pub struct SetRouterArgs {
+    /// Port the router listens on, when it differs from the port clients connect to, e.g.
+    /// behind port forwarding
+    #[structopt(long)]
+    pub listen_port: Option<u16>,
+    /// Port clients connect to, e.g. the public port forwarded to the router. The router keeps
+    /// listening on its current port, unless --listen-port is given.
+    #[structopt(long)]
+    pub endpoint_port: Option<u16>,
+    /// Persistent keepalive given to clients added later without one, in seconds, 0 to remove
+    /// it
+    #[structopt(long = "set-default-keepalive", parse(try_from_str = parse_keepalive))]
+    pub default_keepalive: Option<usize>,
+    /// MTU of the router interface, within 576..=1500
+    #[structopt(long)]
+    pub mtu: Option<u16>,
+    /// Allow an MTU up to 9000, for jumbo frames
+    #[structopt(long, requires = "mtu")]
+    pub jumbo: bool,
+    /// Routing table for the routes of the router interface: off, auto or a table number
+    #[structopt(long, parse(try_from_str = parse_table))]
+    pub table: Option<TableType>,
+    /// Command run by wg-quick before bringing the router interface up
+    #[structopt(long, parse(try_from_str = parse_hook))]
+    pub preup: Option<String>,
+    /// Command run by wg-quick after bringing the router interface up
+    #[structopt(long, parse(try_from_str = parse_hook))]
+    pub postup: Option<String>,
+    /// Command run by wg-quick before bringing the router interface down
+    #[structopt(long, parse(try_from_str = parse_hook))]
+    pub predown: Option<String>,
+    /// Command run by wg-quick after bringing the router interface down
+    #[structopt(long, parse(try_from_str = parse_hook))]
+    pub postdown: Option<String>,
+    /// Firewall mark for outgoing packets, in decimal or 0x-prefixed hexadecimal
+    #[structopt(long, parse(try_from_str = parse_fwmark))]
+    pub fwmark: Option<u32>,
+    /// Let wg-quick persist runtime changes to the interface (true or false)
+    #[structopt(long)]
+    pub save_config: Option<bool>,
+}
+
+pub enum ListFormat {
+    Table,
+    Json,
+}
+
+impl FromStr for ListFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(ListFormat::Table),
+            "json" => Ok(ListFormat::Json),
+            x => Err(format!("unknown format {}", x)),
+        }
+    }
+}
+
+pub enum ListSort {
+    Name,
+    Address,
+    Allowed,
+}
+
+impl FromStr for ListSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(ListSort::Name),
+            "address" => Ok(ListSort::Address),
+            "allowed" => Ok(ListSort::Allowed),
+            x => Err(format!("unknown sort order {}", x)),
+        }
+    }
+}
+
+/// Parses a bare address or a single-host network such as 10.0.1.2/32
+fn parse_host_address(s: &str) -> Result<IpAddr, String> {
+    if let Ok(address) = s.parse() {
+        return Ok(address);
+    }
+
+    let net: IpNet = s
+        .parse()
+        .map_err(|_| format!("{} is neither an address nor a network", s))?;
+
+    if net.prefix_len() != net.max_prefix_len() {
+        return Err(format!(
+            "{} is not a single address, use a /{} prefix",
+            s,
+            net.max_prefix_len()
+        ));
+    }
+
+    Ok(net.addr())
+}
+
+/// Parses an IPv6 host address, as `parse_host_address` does
+fn parse_host_address_v6(s: &str) -> Result<IpAddr, String> {
+    match parse_host_address(s)? {
+        IpAddr::V4(_) => Err(format!("{} is not an IPv6 address", s)),
+        address => Ok(address),
+    }
+}
+
+/// Parses an expiry, either an RFC 3339 date or a duration from now made of a number and a
+/// unit: `m` (minutes), `h` (hours), `d` (days) or `w` (weeks), e.g. `30d`
+fn parse_expiry(s: &str) -> Result<DateTime<Utc>, String> {
+    let unit: fn(i64) -> Option<Duration> = match s.chars().last() {
+        Some('m') => Duration::try_minutes,
+        Some('h') => Duration::try_hours,
+        Some('d') => Duration::try_days,
+        Some('w') => Duration::try_weeks,
+        _ => {
+            return DateTime::parse_from_rfc3339(s)
+                .map(|date| date.with_timezone(&Utc))
+                .map_err(|e| format!("{} is neither an RFC 3339 date nor a duration: {}", s, e))
+        }
+    };
+
+    let count: i64 = s[..s.len() - 1]
+        .parse()
+        .map_err(|_| format!("{} is neither a date nor a duration such as 30d", s))?;
+
+    unit(count)
+        .and_then(|duration| Utc::now().trunc_subsecs(0).checked_add_signed(duration))
+        .ok_or_else(|| format!("{} is too far away", s))
+}
+
+/// Parses a network, a bare address being a single-host network
+fn parse_network(s: &str) -> Result<IpNet, String> {
+    match s.parse::<IpAddr>() {
+        Ok(address) => Ok(IpNet::from(address)),
+        Err(_) => s
+            .parse()
+            .map_err(|_| format!("{} is neither an address nor a network", s)),
+    }
+}
+
+fn parse_domain(s: &str) -> Result<String, String> {
+    if s.is_empty() || s.contains(|c: char| c == ',' || c.is_whitespace()) {
+        return Err(format!("{} is not a valid domain", s));
+    }
+
+    Ok(s.to_string())
+}
+
+/// Accepts a wg-quick hook command, which must fit on a single non-empty line
+fn parse_hook(s: &str) -> Result<String, String> {
+    if s.trim().is_empty() {
+        return Err("the command is empty".to_string());
+    }
+
+    if s.contains('\n') {
+        return Err("the command must fit on a single line".to_string());
+    }
+
+    Ok(s.to_string())
+}
+
+fn parse_keepalive(s: &str) -> Result<usize, String> {
+    let keepalive: usize = s.parse().map_err(|e| format!("{}", e))?;
+
+    match validation::check_keepalive("the client", keepalive) {
+        Some(issue) => Err(issue.message),
+        None => Ok(keepalive),
+    }
+}
+
+fn parse_table(s: &str) -> Result<TableType, String> {
+    let table: TableType = s
+        .parse()
+        .map_err(|_| format!("{} is neither off, auto nor a table number", s))?;
+
+    match validation::check_table("the interface", &table) {
+        Some(issue) if issue.is_error() => Err(issue.message),
+        _ => Ok(table),
+    }
+}
+
+fn parse_fwmark(s: &str) -> Result<u32, std::num::ParseIntError> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_are_off_auto_or_numbers() {
+        assert_eq!(parse_table("off"), Ok(TableType::Off));
+        assert_eq!(parse_table("Auto"), Ok(TableType::Auto));
+        assert_eq!(parse_table("1234"), Ok(TableType::Custom(1234)));
+        // reserved tables are only warned about
+        assert_eq!(parse_table("253"), Ok(TableType::Custom(253)));
+
+        for table in ["0", "-1", "main", ""] {
+            assert!(parse_table(table).is_err(), "{}", table);
+        }
+    }
+
+    #[test]
+    fn host_addresses_may_carry_a_single_host_prefix() {
+        let v4: IpAddr = "10.0.1.2".parse().unwrap();
+        let v6: IpAddr = "fd00::2".parse().unwrap();
+
+        assert_eq!(parse_host_address("10.0.1.2"), Ok(v4));
+        assert_eq!(parse_host_address("10.0.1.2/32"), Ok(v4));
+        assert_eq!(parse_host_address("fd00::2/128"), Ok(v6));
+
+        for address in ["10.0.1.2/24", "fd00::2/64", "10.0.1", "vpn"] {
+            assert!(parse_host_address(address).is_err(), "{}", address);
+        }
+    }
+
+    #[test]
+    fn ipv6_host_addresses_refuse_ipv4() {
+        let v6: IpAddr = "fd00::2".parse().unwrap();
+
+        assert_eq!(parse_host_address_v6("fd00::2"), Ok(v6));
+        assert_eq!(parse_host_address_v6("fd00::2/128"), Ok(v6));
+
+        for address in ["10.0.1.2", "10.0.1.2/32", "fd00::2/64"] {
+            assert!(parse_host_address_v6(address).is_err(), "{}", address);
+        }
+    }
+
+    #[test]
+    fn expiries_are_rfc_3339_dates_or_durations() {
+        let utc: DateTime<Utc> = "2024-05-01T12:00:00Z".parse().unwrap();
+
+        assert_eq!(parse_expiry("2024-05-01T12:00:00Z"), Ok(utc));
+        assert_eq!(parse_expiry("2024-05-01T14:00:00+02:00"), Ok(utc));
+
+        let before = Utc::now().trunc_subsecs(0);
+        let expiry = parse_expiry("30d").unwrap();
+        let after = Utc::now();
+
+        assert!(before + Duration::days(30) <= expiry);
+        assert!(expiry <= after + Duration::days(30));
+        assert_eq!(expiry, expiry.trunc_subsecs(0));
+
+        for s in [
+            "",
+            "2024-05-01",
+            "2024-05-01T12:00:00",
+            "2024-02-30T12:00:00Z",
+            "2024-05-01T12:00:00+99:99",
+            "xd",
+            "30y",
+            "9223372036854775807w",
+        ] {
+            assert!(parse_expiry(s).is_err(), "{}", s);
+        }
+    }
 }
@@ -0,0 +1,151 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+/// Directives which are not understood by every wireguard-tools release, along with the
+/// first release (wireguard-tools versioning) supporting them
+const CAPABILITIES: &[(&str, &str)] = &[
+    ("FwMark", "0.0.20170409"),
+    ("SaveConfig", "0.0.20170613"),
+    ("Table", "0.0.20171011"),
+];
+
+/// A dotted wireguard-tools version, e.g. 1.0 or 1.0.20210914
+#[derive(Clone, Debug)]
+pub struct WgVersion(Vec<u64>);
+
+impl FromStr for WgVersion {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().trim_start_matches('v');
+
+        s.split('.')
+            .map(|component| component.parse())
+            .collect::<Result<Vec<u64>, _>>()
+            .map(WgVersion)
+    }
+}
+
+impl fmt::Display for WgVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let components = self
+            .0
+            .iter()
+            .map(|component| component.to_string())
+            .collect::<Vec<String>>();
+
+        write!(f, "{}", components.join("."))
+    }
+}
+
+impl Ord for WgVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // missing components count as zeroes, so that 1.0 == 1.0.0
+        let len = self.0.len().max(other.0.len());
+        let component = |version: &WgVersion, i: usize| version.0.get(i).copied().unwrap_or(0);
+
+        (0..len)
+            .map(|i| component(self, i).cmp(&component(other, i)))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialEq for WgVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for WgVersion {}
+
+impl PartialOrd for WgVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Returns a warning for each directive in a rendered configuration which is unavailable
+/// in the target wireguard-tools version
+pub fn compatibility_warnings(conf: &str, target: &WgVersion) -> Vec<String> {
+    let mut warnings: Vec<String> = Vec::new();
+
+    for line in conf.lines().map(str::trim) {
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let key = match line.split_once('=') {
+            Some((key, _)) => key.trim(),
+            None => continue,
+        };
+
+        for (directive, since) in CAPABILITIES {
+            let since: WgVersion = since.parse().expect("Invalid capability version.");
+
+            if key.eq_ignore_ascii_case(directive) && *target < since {
+                let warning = format!(
+                    "{} is not supported before wireguard-tools {} (target is {})",
+                    directive, since, target
+                );
+
+                if !warnings.contains(&warning) {
+                    warnings.push(warning);
+                }
+            }
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTER_CONF: &str = "\
+# vpn
+[Interface]
+PrivateKey = cm91dGVyLXByaXZhdGUta2V5LTAwMDAwMDAwMDAwMDA=
+Address = 10.0.1.1/24
+ListenPort = 51820
+FwMark = 0xca6c
+";
+
+    fn version(s: &str) -> WgVersion {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn versions_compare_component_wise() {
+        assert_eq!(version("v1.0"), version("1.0.0"));
+        assert!(version("0.0.20170409") < version("0.0.20170613"));
+        assert!(version("0.0.20191226") < version("1.0"));
+        assert!("1.x".parse::<WgVersion>().is_err());
+    }
+
+    #[test]
+    fn fwmark_warns_for_an_older_target() {
+        let warnings = compatibility_warnings(ROUTER_CONF, &version("0.0.20170101"));
+
+        assert_eq!(
+            warnings,
+            ["FwMark is not supported before wireguard-tools 0.0.20170409 (target is 0.0.20170101)"]
+        );
+    }
+
+    #[test]
+    fn fwmark_passes_for_a_recent_target() {
+        assert!(compatibility_warnings(ROUTER_CONF, &version("0.0.20170409")).is_empty());
+        assert!(compatibility_warnings(ROUTER_CONF, &version("1.0.20210914")).is_empty());
+    }
+
+    #[test]
+    fn comments_are_not_directives() {
+        let conf = "# Table = off\n[Interface]\nAddress = 10.0.1.2/32\n";
+
+        assert!(compatibility_warnings(conf, &version("0.0.1")).is_empty());
+    }
+}
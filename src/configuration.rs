@@ -1,16 +1,24 @@
-use crate::endpoint::{Peer, Router};
-use serde_yaml;
+use crate::addrport::AddrPort;
+use crate::endpoint::{Peer, Router, NAT_KEEPALIVE, REDACTED_KEY};
+use crate::error::ConfigError;
+use crate::keys;
+use crate::validation::{self, Issue};
+use chrono::{SecondsFormat, Utc};
+use ipnet::IpNet;
 use std::error::Error;
 use std::fmt;
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv6Addr};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use structopt::StructOpt;
 
 #[derive(Clone, Debug, Deserialize, Serialize, StructOpt)]
 #[structopt(flatten)]
 pub struct ConfigOpts {
-    /// A wireguard-configure configuration file name found in /etc/wireguard. The file must end in .toml.
+    /// A wireguard-configure configuration file name found in /etc/wireguard, or in the
+    /// directory set by WG_CONFIGURE_DIR. The file must end in .toml.
     /// A configuration is named after its file stem.
     ///
     /// e.g: wg0 -> /etc/wireguard/wg0.toml
@@ -27,42 +35,154 @@ pub struct ConfigOpts {
     pub path: Option<PathBuf>,
 }
 
+/// Directory where configurations are looked up by name, unless overridden by the
+/// `CONFIG_DIR_ENV` environment variable
+pub const DEFAULT_CONFIG_DIR: &str = "/etc/wireguard";
+pub const CONFIG_DIR_ENV: &str = "WG_CONFIGURE_DIR";
+/// Where WireGuard for Windows keeps its configurations, below the program files directory
+const WINDOWS_CONFIG_DIR: &str = r"WireGuard\Data\Configurations";
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Configuration {
     // Do not serialize metadata
     #[serde(skip_serializing)]
     pub metadata: Option<ConfigOpts>,
+    // Whether private keys are printed when previewing a dry run
+    #[serde(skip)]
+    pub reveal_secrets: bool,
+    // Whether saving prints the configuration to stdout even when it has a file
+    #[serde(skip)]
+    pub dry_run: bool,
+    // Whether rendered .conf files start with the tool version, date and configuration name
+    #[serde(skip)]
+    pub verbose_comments: bool,
+    // Format the configuration is written in
+    #[serde(skip)]
+    pub format: ConfigFormat,
     pub router: Router,
     pub clients: Vec<Peer>,
+    /// Persistent keepalive given to new clients which do not set one
+    #[serde(default)]
+    pub default_keepalive: Option<usize>,
+    /// Addresses never assigned automatically, e.g. statically used ones
+    #[serde(default)]
+    pub reserved: Vec<IpNet>,
+}
+
+/// Usage of the addresses of the router subnet
+#[derive(Clone, Debug)]
+pub struct PoolUsage {
+    pub subnet: IpNet,
+    /// Number of host addresses, saturating at `u128::MAX` for a whole IPv6 space
+    pub total: u128,
+    /// Number of host addresses taken by the router or a client
+    pub used: u128,
+    pub next_available: Option<IpAddr>,
+}
+
+impl PoolUsage {
+    /// Share of used host addresses, in percent
+    pub fn percentage(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+
+        self.used as f64 * 100.0 / self.total as f64
+    }
+}
+
+/// A format configurations are written in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConfigFormat {
+    #[default]
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// The format of a configuration file: JSON for a `.json` file, TOML for a `.toml` file,
+    /// YAML otherwise, whatever the extension
+    pub fn for_path(path: &Path) -> ConfigFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => ConfigFormat::Json,
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yaml" => Ok(ConfigFormat::Yaml),
+            "toml" => Ok(ConfigFormat::Toml),
+            "json" => Ok(ConfigFormat::Json),
+            x => Err(format!("unknown format {}", x)),
+        }
+    }
 }
 
 impl fmt::Display for Configuration {
+    /// Formats the configuration as YAML, with private keys redacted
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            serde_yaml::to_string(&self).expect("Failed to serialize configuration.")
-        )
+        write!(f, "{}", self.to_yaml(false))
     }
 }
 
 impl Configuration {
-    pub fn from_path(path: &Path) -> Result<Configuration, Box<dyn Error>> {
-        let mut file = File::open(path)?;
+    /// Reads the configuration file at `path`, in the format of its extension, see
+    /// `ConfigFormat::for_path`
+    pub fn from_path(path: &Path) -> Result<Configuration, ConfigError> {
+        Configuration::read_path(path, None)
+    }
+
+    /// Reads the configuration file at `path` in `format`, whatever its extension
+    pub fn from_path_as(path: &Path, format: ConfigFormat) -> Result<Configuration, ConfigError> {
+        Configuration::read_path(path, Some(format))
+    }
+
+    fn read_path(path: &Path, format: Option<ConfigFormat>) -> Result<Configuration, ConfigError> {
+        let mut file = File::open(path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => ConfigError::NotFound(path.to_path_buf()),
+            io::ErrorKind::PermissionDenied => ConfigError::PermissionDenied(path.to_path_buf()),
+            _ => ConfigError::Io(e),
+        })?;
         let mut buffer: String = String::new();
 
         // extracting the configuration name from the file stem, if valid
         let config_name = path
             .file_stem()
-            .expect("Invalid file stem.")
-            .to_str()
-            .expect("Cannot parse file stem.");
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| ConfigError::InvalidName(path.to_path_buf()))?;
 
         // reading file contents
         file.read_to_string(&mut buffer)?;
 
         // deserializing file contents
-        let buf_config: Configuration = serde_yaml::from_str(&buffer)?;
+        let buf_config = match format.unwrap_or_else(|| ConfigFormat::for_path(path)) {
+            // configurations named after their .toml file used to be written in YAML
+            ConfigFormat::Toml if format.is_none() => Configuration::from_str_any(&buffer)?,
+            format => Configuration::from_str_as(&buffer, format)?,
+        };
+
+        // private keys must not be readable by other users
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = file.metadata()?.permissions().mode();
+
+            if mode & 0o044 != 0 && buf_config.has_private_keys() {
+                eprintln!(
+                    "warning: {} holds private keys but is readable by other users, restrict it with `chmod 600 {}`",
+                    path.display(),
+                    path.display()
+                );
+            }
+        }
 
         // adding metadata to config
         let config = buf_config.with_name(config_name).with_path(path);
@@ -70,33 +190,286 @@ impl Configuration {
         Ok(config)
     }
 
-    pub fn save(&self) -> Result<(), Box<dyn Error>> {
-        if self.is_from_tty() {
-            println!("{}", self);
+    /// Whether the router or a client has its private key stored in the configuration
+    pub fn has_private_keys(&self) -> bool {
+        self.router.private_key.is_some()
+            || self
+                .clients
+                .iter()
+                .any(|client| client.private_key.is_some())
+    }
+
+    /// Parses a configuration written in `format`, which is kept to write it back
+    pub fn from_str_as(text: &str, format: ConfigFormat) -> Result<Configuration, ConfigError> {
+        let config: Configuration = match format {
+            ConfigFormat::Yaml => serde_yaml::from_str(text)?,
+            ConfigFormat::Toml => toml::from_str(text)?,
+            ConfigFormat::Json => serde_json::from_str(text)?,
+        };
+
+        config.check_not_redacted()?;
+
+        Ok(config.with_format(format))
+    }
+
+    /// Refuses a configuration whose private keys were redacted, as saving it would lose them
+    fn check_not_redacted(&self) -> Result<(), ConfigError> {
+        let owners = std::iter::once((&self.router.name, &self.router.private_key)).chain(
+            self.clients
+                .iter()
+                .map(|client| (&client.name, &client.private_key)),
+        );
+
+        for (owner, private_key) in owners {
+            if private_key.as_deref() == Some(REDACTED_KEY) {
+                return Err(ConfigError::RedactedKey(owner.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses a configuration written either in TOML or in YAML, e.g. when piped to stdin. It is
+    /// saved back in the format it was read in.
+    pub fn from_str_any(text: &str) -> Result<Configuration, ConfigError> {
+        let toml_error = match toml::from_str::<Configuration>(text) {
+            Ok(config) => {
+                config.check_not_redacted()?;
+
+                return Ok(config.with_format(ConfigFormat::Toml));
+            }
+            Err(e) => e,
+        };
+
+        let config: Configuration =
+            serde_yaml::from_str(text).map_err(|yaml_error| ConfigError::UnknownFormat {
+                // the full TOML error spans several lines, quoting the faulty one
+                toml: toml_error.message().to_string(),
+                yaml: yaml_error.to_string(),
+            })?;
+
+        config.check_not_redacted()?;
+
+        Ok(config.with_format(ConfigFormat::Yaml))
+    }
+
+    /// The path of the configuration named `name`, i.e. `<name>.toml` in the configuration
+    /// directory
+    pub fn path_for_name(name: &str) -> PathBuf {
+        let dir = std::env::var_os(CONFIG_DIR_ENV)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(Configuration::default_config_dir);
+
+        dir.join(format!("{}.toml", name))
+    }
+
+    /// The platform's WireGuard directory: `DEFAULT_CONFIG_DIR`, or the configurations
+    /// directory of WireGuard for Windows
+    fn default_config_dir() -> PathBuf {
+        if !cfg!(windows) {
+            return PathBuf::from(DEFAULT_CONFIG_DIR);
+        }
+
+        std::env::var_os("ProgramFiles")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\Program Files"))
+            .join(WINDOWS_CONFIG_DIR)
+    }
+
+    /// Parses a wg-quick configuration of a router: the `[Interface]` becomes the router and
+    /// each `[Peer]` a client. Names are taken from the `# comment` line preceding each section,
+    /// and the `Endpoint` of a peer becomes its mesh endpoint.
+    ///
+    /// The router's external address cannot be known from a wg-quick configuration and is left
+    /// empty. Nor can the subnets the clients route through the VPN, which are
+    /// `client_allowed_ips`, or the router subnets if empty.
+    pub fn from_wg_conf(
+        text: &str,
+        client_allowed_ips: &[IpNet],
+    ) -> Result<Configuration, Box<dyn Error>> {
+        let sections = parse_wg_sections(text)?;
+
+        let mut interfaces = sections
+            .iter()
+            .filter(|section| section.kind == "Interface");
+        let interface = match (interfaces.next(), interfaces.next()) {
+            (Some(interface), None) => interface,
+            (None, _) => Err("No [Interface] section found.")?,
+            (Some(_), Some(_)) => Err("More than one [Interface] section found.")?,
+        };
+
+        let private_key = interface.required("PrivateKey")?.to_string();
+        keys::validate_wg_key(&private_key)
+            .map_err(|e| format!("Invalid private key in [Interface]: {}", e))?;
+        let public_key = keys::pubkey_from_privkey(&private_key)?;
+        let addresses = interface
+            .required("Address")?
+            .split(',')
+            .map(|address| address.trim().parse())
+            .collect::<Result<Vec<IpNet>, _>>()?;
+        let internal_address = match addresses.first() {
+            Some(address) => *address,
+            None => Err("No address in [Interface] section.")?,
+        };
+        // a second address of the other family makes a dual-stack router
+        let internal_address_v6 = match internal_address {
+            IpNet::V4(_) => addresses
+                .iter()
+                .find(|address| matches!(address, IpNet::V6(_)))
+                .copied(),
+            IpNet::V6(_) => None,
+        };
+        let listen_port = match interface.get("ListenPort") {
+            Some(port) => port.parse()?,
+            None => 51820,
+        };
+
+        let mut router = Router::from_keys(
+            interface.name.as_deref().unwrap_or("router"),
+            internal_address,
+            AddrPort::new("", listen_port),
+            private_key,
+            public_key,
+        )
+        .with_internal_address_v6(internal_address_v6);
+
+        router.mtu = interface.get("MTU").map(str::parse).transpose()?;
+        router.table = interface.get("Table").map(str::parse).transpose()?;
+        router.save_config = interface.get("SaveConfig").map(str::parse).transpose()?;
+        router.preup = interface.get("PreUp").map(str::to_string);
+        router.postup = interface.get("PostUp").map(str::to_string);
+        router.predown = interface.get("PreDown").map(str::to_string);
+        router.postdown = interface.get("PostDown").map(str::to_string);
+        router.fwmark = match interface.get("FwMark") {
+            Some("off") => None,
+            Some(fwmark) => match fwmark.strip_prefix("0x") {
+                Some(hex) => Some(u32::from_str_radix(hex, 16)?),
+                None => Some(fwmark.parse()?),
+            },
+            None => None,
+        };
+
+        let client_allowed_ips = match client_allowed_ips {
+            [] => router.subnets(),
+            allowed_ips => allowed_ips.to_vec(),
+        };
+        let mut configuration = Configuration::new(router);
+
+        for (i, section) in sections
+            .iter()
+            .filter(|section| section.kind == "Peer")
+            .enumerate()
+        {
+            let public_key = section.required("PublicKey")?.to_string();
+            keys::validate_wg_key(&public_key)
+                .map_err(|e| format!("Invalid public key {}: {}", public_key, e))?;
+            let allowed_ips = section
+                .required("AllowedIPs")?
+                .split(',')
+                .map(|ip| ip.trim().parse())
+                .collect::<Result<Vec<IpNet>, _>>()?;
+
+            // the peer's own address is its host route, if any
+            let internal_address = match allowed_ips
+                .iter()
+                .find(|ip| ip.prefix_len() == ip.max_prefix_len())
+                .or_else(|| allowed_ips.first())
+            {
+                Some(ip) => ip.addr(),
+                None => Err(format!("Peer {} has no allowed IPs.", public_key))?,
+            };
+
+            // as well as its IPv6 host route for a dual-stack router
+            let internal_address_v6 = match (internal_address, internal_address_v6) {
+                (IpAddr::V4(_), Some(_)) => allowed_ips
+                    .iter()
+                    .find(|ip| matches!(ip, IpNet::V6(_)) && ip.prefix_len() == 128)
+                    .map(IpNet::addr),
+                _ => None,
+            };
+
+            // any other allowed IP is a network behind the peer
+            let host_routes: Vec<IpNet> = std::iter::once(internal_address)
+                .chain(internal_address_v6)
+                .map(IpNet::from)
+                .collect();
+            let routed_subnets = allowed_ips
+                .iter()
+                .filter(|ip| !host_routes.contains(ip))
+                .copied()
+                .collect();
+
+            let name = match &section.name {
+                Some(name) => name.clone(),
+                None => format!("peer-{}", i + 1),
+            };
+
+            let endpoint = section.get("Endpoint").map(str::parse).transpose()?;
+
+            let peer = Peer::from_keys(name, internal_address, None, public_key)
+                .with_internal_address_v6(internal_address_v6)
+                .with_vec_allowed_ips(client_allowed_ips.clone())
+                .with_mesh_endpoint(endpoint)
+                .with_routed_subnets(routed_subnets)
+                .with_keepalive(
+                    section
+                        .get("PersistentKeepalive")
+                        .filter(|keepalive| *keepalive != "off")
+                        .map(str::parse)
+                        .transpose()?,
+                );
+
+            configuration.push_peer(peer);
+        }
+
+        Ok(configuration)
+    }
+
+    pub fn save(&self) -> Result<(), ConfigError> {
+        for (a, b, net) in self.check_allowed_ip_overlaps() {
+            eprintln!("warning: allowed IPs of {} and {} overlap on {}", a, b, net);
+        }
+
+        for issue in self.check_duplicate_public_keys() {
+            eprintln!("warning: {}", issue.message);
+        }
+
+        if self.writes_to_stdout() {
+            // a piped configuration is saved to stdout, while a dry run is only a preview
+            let reveal_secrets = !self.dry_run || self.reveal_secrets;
+
+            println!("{}", self.to_format(self.format, reveal_secrets));
             return Ok(());
         }
 
         // extracting path from metadata
-        let path = match &self.metadata {
-            None => return Err("Configuration metadata not found.")?,
-            Some(metadata) => match &metadata.path {
-                None => return Err("No path defined for this configuration.")?,
-                Some(path) => path,
-            },
+        let path = match self
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.path.as_ref())
+        {
+            Some(path) => path,
+            None => return Err(ConfigError::NoPath),
         };
 
-        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
-        let bytes = serde_yaml::to_string(&self).expect("Failed to serialize configuration");
+        replace_private_file(path, &self.to_format(self.format, true))?;
 
-        file.write_all(bytes.as_bytes())?;
         Ok(())
     }
 
     pub fn new(router: Router) -> Configuration {
         Configuration {
             metadata: None,
+            reveal_secrets: false,
+            dry_run: false,
+            verbose_comments: false,
+            format: ConfigFormat::Yaml,
             router,
             clients: Vec::new(),
+            default_keepalive: None,
+            reserved: Vec::new(),
         }
     }
 
@@ -117,6 +490,26 @@ impl Configuration {
         self
     }
 
+    pub fn with_reveal_secrets(mut self, reveal_secrets: bool) -> Configuration {
+        self.reveal_secrets = reveal_secrets;
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Configuration {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn with_format(mut self, format: ConfigFormat) -> Configuration {
+        self.format = format;
+        self
+    }
+
+    pub fn with_verbose_comments(mut self, verbose_comments: bool) -> Configuration {
+        self.verbose_comments = verbose_comments;
+        self
+    }
+
     pub fn with_path(mut self, path: &Path) -> Configuration {
         match &mut self.metadata {
             Some(metadata) => metadata.path = Some(path.to_path_buf()),
@@ -131,23 +524,1540 @@ impl Configuration {
         self
     }
 
+    /// Adds `client` as is, without any check. See `add_peer` for the checked version.
     pub fn push_peer(&mut self, client: Peer) {
         self.clients.push(client);
     }
 
+    /// Adds `client` once its name and addresses are known not to be taken, and its
+    /// addresses to be host addresses of the router subnets
+    pub fn add_peer(&mut self, client: Peer) -> Result<(), ConfigError> {
+        self.check_peer_conflicts(&client)?;
+
+        let subnets = self.router.subnets();
+
+        for address in client.internal_addresses() {
+            match subnets
+                .iter()
+                .find(|subnet| subnet.addr().is_ipv4() == address.is_ipv4())
+            {
+                Some(subnet) if is_host(&subnet.trunc(), &address) => {}
+                Some(subnet) if subnet.contains(&address) => Err(ConfigError::NotHostAddress {
+                    address,
+                    subnet: *subnet,
+                })?,
+                Some(subnet) => Err(ConfigError::OutsideSubnet {
+                    address,
+                    subnet: *subnet,
+                })?,
+                None => Err(ConfigError::NoSubnet(address))?,
+            }
+        }
+
+        self.push_peer(client);
+        Ok(())
+    }
+
+    /// Removes the client named `name`, returning it if there was one
+    pub fn remove_peer(&mut self, name: &str) -> Option<Peer> {
+        let index = self.clients.iter().position(|client| client.name == name)?;
+
+        Some(self.clients.remove(index))
+    }
+
+    /// Checks that neither the router nor another client already uses the name or one of the
+    /// addresses of `client`
+    pub fn check_peer_conflicts(&self, client: &Peer) -> Result<(), ConfigError> {
+        if self.client_by_name(&client.name).is_some() {
+            return Err(ConfigError::DuplicateName(client.name.clone()));
+        }
+
+        for address in client.internal_addresses() {
+            if self
+                .router
+                .internal_addresses()
+                .iter()
+                .any(|router_address| router_address.addr() == address)
+            {
+                return Err(ConfigError::DuplicateAddress {
+                    address,
+                    owner: self.router.name.clone(),
+                });
+            }
+
+            if let Some(owner) = self
+                .clients
+                .iter()
+                .find(|other| other.internal_addresses().contains(&address))
+            {
+                return Err(ConfigError::DuplicateAddress {
+                    address,
+                    owner: owner.name.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reserves `net`, unless already reserved, returning whether it was added
+    pub fn reserve(&mut self, net: IpNet) -> bool {
+        if self.reserved.contains(&net) {
+            return false;
+        }
+
+        self.reserved.push(net);
+        true
+    }
+
+    /// Releases the reservation of `net`, returning whether it was reserved
+    pub fn unreserve(&mut self, net: &IpNet) -> bool {
+        let count = self.reserved.len();
+
+        self.reserved.retain(|reserved| reserved != net);
+        self.reserved.len() != count
+    }
+
+    /// Removes the expired clients, and the disabled ones if `include_disabled`, returning
+    /// them
+    pub fn prune(&mut self, include_disabled: bool) -> Vec<Peer> {
+        let (pruned, kept) = self
+            .clients
+            .drain(..)
+            .partition(|client| client.is_expired() || (include_disabled && !client.enabled));
+
+        self.clients = kept;
+        pruned
+    }
+
     pub fn client_by_name(&self, name: &str) -> Option<&Peer> {
         self.clients.iter().find(|client| client.name == name)
     }
 
-    pub fn client_config(&self, name: &str) -> Option<String> {
-        let client = self.client_by_name(name)?;
+    /// An address of the router subnet used neither by the router nor by a client, and not
+    /// reserved, see `next_available_in`
+    pub fn next_available_address(&self) -> Option<IpAddr> {
+        next_available_in(
+            self.router.internal_address,
+            &self.used_addresses(),
+            &self.reserved,
+        )
+    }
+
+    /// An address of the IPv6 subnet of a dual-stack router used neither by the router nor
+    /// by a client, and not reserved, see `next_available_in`
+    pub fn next_available_address_v6(&self) -> Option<IpAddr> {
+        next_available_in(
+            self.router.internal_address_v6?,
+            &self.used_addresses(),
+            &self.reserved,
+        )
+    }
+
+    /// Whether `address` can be given to a new client: it is a host address of the router
+    /// subnet of its family, used by no one and not reserved
+    pub fn is_address_free(&self, address: IpAddr) -> bool {
+        self.router
+            .subnets()
+            .iter()
+            .any(|subnet| is_host(&subnet.trunc(), &address))
+            && !self.used_addresses().contains(&address)
+            && !self.reserved.iter().any(|net| net.contains(&address))
+    }
+
+    /// Every address of the router and of the clients
+    fn used_addresses(&self) -> Vec<IpAddr> {
+        self.router
+            .internal_addresses()
+            .iter()
+            .map(IpNet::addr)
+            .chain(self.clients.iter().flat_map(Peer::internal_addresses))
+            .collect()
+    }
+
+    /// Counts the host addresses of the router subnet and how many of them are used
+    pub fn pool_usage(&self) -> PoolUsage {
+        let subnet = self.router.internal_address.trunc();
+        let mut used: Vec<IpAddr> = std::iter::once(self.router.internal_address.addr())
+            .chain(self.clients.iter().map(|client| client.internal_address))
+            .filter(|address| is_host(&subnet, address))
+            .collect();
+
+        used.sort();
+        used.dedup();
+
+        PoolUsage {
+            subnet,
+            total: host_count(&subnet),
+            used: used.len() as u128,
+            next_available: self.next_available_address(),
+        }
+    }
+
+    pub fn client_config(&self, name: &str) -> Result<Option<String>, io::Error> {
+        self.client_config_for_endpoint(name, None)
+    }
+
+    /// Renders the client configuration reaching the router at its endpoint `index`, see
+    /// `Router::endpoints`. Without an index, the client's endpoint override is used, if any.
+    /// Fails if a key file cannot be read.
+    pub fn client_config_for_endpoint(
+        &self,
+        name: &str,
+        index: Option<usize>,
+    ) -> Result<Option<String>, io::Error> {
+        self.render_client_config(name, index, false)
+    }
+
+    /// Renders the client configuration like `client_config_for_endpoint`, with the endpoint
+    /// resolved to an address of the client's family, for clients whose DNS is unreliable.
+    /// Fails if the endpoint does not resolve.
+    pub fn client_config_resolved(
+        &self,
+        name: &str,
+        index: Option<usize>,
+    ) -> Result<Option<String>, io::Error> {
+        self.render_client_config(name, index, true)
+    }
+
+    fn render_client_config(
+        &self,
+        name: &str,
+        index: Option<usize>,
+        resolve: bool,
+    ) -> Result<Option<String>, io::Error> {
+        let client = match self.client_by_name(name) {
+            Some(client) if client.is_complete() => client,
+            _ => return Ok(None),
+        };
+
+        let endpoint = match index.map(|index| self.router.endpoint(index)) {
+            Some(Some(endpoint)) => endpoint,
+            Some(None) => return Ok(None),
+            None => client
+                .endpoint_override
+                .as_ref()
+                .unwrap_or(&self.router.external_address),
+        };
+
+        let peer = if resolve {
+            let endpoint = endpoint.resolve(client.internal_address.is_ipv6())?;
+
+            client.peer_str_for_endpoint(&self.router, &endpoint)?
+        } else {
+            client.peer_str_for_endpoint(&self.router, endpoint)?
+        };
+
+        Ok(client
+            .interface_str()?
+            .map(|interface| self.with_header(format!("{}\n\n{}", interface, peer))))
+    }
+
+    /// Renders one configuration per node of a full mesh, the router and the enabled clients,
+    /// each node having a `[Peer]` for every other one. Nodes are named after their router or
+    /// client, clients whose private key is unknown only appear as peers.
+    pub fn mesh_configs(&self) -> Result<Vec<(String, String)>, io::Error> {
+        let clients: Vec<&Peer> = self
+            .clients
+            .iter()
+            .filter(|client| client.enabled)
+            .collect();
+        let mut nodes = vec![MeshNode {
+            name: &self.router.name,
+            public_key: self.router.load_public_key()?,
+            endpoint: Some(&self.router.external_address),
+            allowed_ips: self
+                .router
+                .internal_addresses()
+                .iter()
+                .map(|address| IpNet::from(address.addr()))
+                .collect(),
+        }];
+
+        for client in &clients {
+            nodes.push(MeshNode {
+                name: &client.name,
+                public_key: client.load_public_key()?,
+                endpoint: client.mesh_endpoint.as_ref(),
+                allowed_ips: client.router_allowed_ips(),
+            });
+        }
+
+        // the peers of the node at `index`, i.e. every other node
+        let peers = |index: usize, keepalive: Option<usize>| {
+            nodes
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != index)
+                .map(|(_, node)| node.peer_str(keepalive))
+                .collect::<Vec<String>>()
+        };
+
+        let mut configs = Vec::new();
+        let router_interface = self.router.interface_str()?;
+
+        configs.push((
+            self.router.name.clone(),
+            self.with_header(
+                std::iter::once(router_interface)
+                    .chain(peers(0, None))
+                    .collect::<Vec<String>>()
+                    .join("\n\n"),
+            ),
+        ));
+
+        for (i, client) in clients.iter().enumerate() {
+            if let Some(interface) = client.interface_str()? {
+                configs.push((
+                    client.name.clone(),
+                    self.with_header(
+                        std::iter::once(interface)
+                            .chain(peers(i + 1, client.keepalive()))
+                            .collect::<Vec<String>>()
+                            .join("\n\n"),
+                    ),
+                ));
+            }
+        }
+
+        Ok(configs)
+    }
+
+    /// Runs every static check on the configuration
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = self.check_duplicates();
+
+        issues.extend(self.check_duplicate_public_keys());
+        issues.extend(self.check_subnet_membership());
+        issues.extend(self.check_subnet_alignment());
+        issues.extend(self.check_key_format());
+        issues.extend(self.check_mtus());
+        issues.extend(self.check_tables());
+        issues.extend(self.check_keepalives());
+        issues.extend(self.check_allowed_ips());
+        issues.extend(self.check_expiry());
+
+        for client in &self.clients {
+            issues.extend(self.check_listen_port(client));
+        }
+
+        for (a, b, net) in self.check_allowed_ip_overlaps() {
+            issues.push(Issue::warning(format!(
+                "allowed IPs of {} and {} overlap on {}",
+                a, b, net
+            )));
+        }
+
+        issues
+    }
+
+    /// Checks that no two clients share a name or an internal address
+    pub fn check_duplicates(&self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+
+        for (i, a) in self.clients.iter().enumerate() {
+            for b in &self.clients[i + 1..] {
+                if a.name == b.name {
+                    issues.push(Issue::error(format!(
+                        "more than one client is named {}",
+                        a.name
+                    )));
+                }
+
+                let b_addresses = b.internal_addresses();
+
+                for address in a
+                    .internal_addresses()
+                    .iter()
+                    .filter(|address| b_addresses.contains(address))
+                {
+                    issues.push(Issue::error(format!(
+                        "clients {} and {} share the internal address {}",
+                        a.name, b.name, address
+                    )));
+                }
+            }
+        }
+
+        issues
+    }
+
+    /// Checks that no two peers, router included, share a public key, as wg only keeps the
+    /// last of them. Keys which cannot be loaded are left to `check_key_format`.
+    pub fn check_duplicate_public_keys(&self) -> Vec<Issue> {
+        let mut owners: Vec<(String, Vec<&str>)> = Vec::new();
+        let router = self
+            .router
+            .load_public_key()
+            .map(|key| (self.router.name.as_str(), key));
+        let clients = self.clients.iter().filter_map(|client| {
+            client
+                .load_public_key()
+                .ok()
+                .map(|key| (client.name.as_str(), key))
+        });
+
+        for (name, key) in router.into_iter().chain(clients) {
+            match owners.iter_mut().find(|(owned, _)| *owned == key) {
+                Some((_, names)) => names.push(name),
+                None => owners.push((key, vec![name])),
+            }
+        }
+
+        owners
+            .into_iter()
+            .filter(|(_, names)| names.len() > 1)
+            .map(|(key, names)| {
+                Issue::error(format!("{} share the public key {}", names.join(", "), key))
+            })
+            .collect()
+    }
+
+    /// Checks that every client's internal address belongs to the router subnet
+    pub fn check_subnet_membership(&self) -> Vec<Issue> {
+        self.clients
+            .iter()
+            .flat_map(|client| self.check_peer_membership(client))
+            .collect()
+    }
+
+    /// Checks that the peer's internal addresses belong to the router subnets, as the router
+    /// cannot reach them otherwise
+    pub fn check_peer_membership(&self, peer: &Peer) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let subnet = self.router.internal_address;
+
+        if !subnet.contains(&peer.internal_address) {
+            issues.push(Issue::error(format!(
+                "client {}'s address {} is outside of the router subnet {}",
+                peer.name,
+                peer.internal_address,
+                subnet.trunc()
+            )));
+        }
+
+        match (peer.internal_address_v6, self.router.internal_address_v6) {
+            (Some(address), Some(subnet)) if !subnet.contains(&address) => {
+                issues.push(Issue::error(format!(
+                    "client {}'s address {} is outside of the router subnet {}",
+                    peer.name,
+                    address,
+                    subnet.trunc()
+                )))
+            }
+            (Some(address), None) => issues.push(Issue::error(format!(
+                "client {} has the IPv6 address {} but the router has no IPv6 subnet",
+                peer.name, address
+            ))),
+            _ => {}
+        }
+
+        issues
+    }
+
+    /// Checks that every client routes something through the VPN, which is not the case with
+    /// hand-edited or imported configurations lacking allowed IPs
+    pub fn check_allowed_ips(&self) -> Vec<Issue> {
+        self.clients
+            .iter()
+            .filter_map(|client| self.check_peer_allowed_ips(client))
+            .collect()
+    }
+
+    /// Warns if `peer` has no allowed IPs. Its configuration is still valid, but routes
+    /// nothing through the VPN.
+    pub fn check_peer_allowed_ips(&self, peer: &Peer) -> Option<Issue> {
+        if !peer.allowed_ips.is_empty() {
+            return None;
+        }
+
+        Some(Issue::warning(format!(
+            "client {} has no allowed IPs, allow at least the router address {}/{}",
+            peer.name,
+            self.router.internal_address.addr(),
+            self.router.internal_address.max_prefix_len()
+        )))
+    }
+
+    /// Reports the clients whose access has ended, which are still in the configuration
+    pub fn check_expiry(&self) -> Vec<Issue> {
+        self.clients
+            .iter()
+            .filter(|client| client.is_expired())
+            .filter_map(|client| {
+                let expires_at = client.expires_at?;
+
+                Some(Issue::warning(format!(
+                    "client {} expired on {}",
+                    client.name, expires_at
+                )))
+            })
+            .collect()
+    }
+
+    /// Checks that every MTU set is within the range of jumbo frames, and at least the IPv6
+    /// minimum for the router and clients with an IPv6 address
+    pub fn check_mtus(&self) -> Vec<Issue> {
+        std::iter::once((&self.router.name, self.router.mtu, self.router.has_ipv6()))
+            .chain(
+                self.clients
+                    .iter()
+                    .map(|client| (&client.name, client.mtu, client.has_ipv6())),
+            )
+            .filter_map(|(name, mtu, ipv6)| validation::check_mtu(name, mtu?, true, ipv6))
+            .collect()
+    }
+
+    /// Checks that every custom routing table set can hold routes
+    pub fn check_tables(&self) -> Vec<Issue> {
+        std::iter::once((&self.router.name, &self.router.table))
+            .chain(
+                self.clients
+                    .iter()
+                    .map(|client| (&client.name, &client.table)),
+            )
+            .filter_map(|(name, table)| validation::check_table(name, table.as_ref()?))
+            .collect()
+    }
+
+    /// Checks that every persistent keepalive set, including the default one, is at most
+    /// `MAX_KEEPALIVE`, and that no client behind NAT has its keepalive disabled, as the NAT
+    /// mapping would expire while it is idle
+    pub fn check_keepalives(&self) -> Vec<Issue> {
+        let default = self
+            .default_keepalive
+            .and_then(|keepalive| validation::check_keepalive("the default", keepalive));
+        let disabled_behind_nat = self
+            .clients
+            .iter()
+            .filter(|client| client.behind_nat && client.keepalive().is_none())
+            .map(|client| {
+                Issue::warning(format!(
+                    "client {} is behind NAT but has no persistent keepalive, the router cannot reach it once idle, set one such as {}",
+                    client.name, NAT_KEEPALIVE
+                ))
+            });
+
+        default
+            .into_iter()
+            .chain(self.clients.iter().filter_map(|client| {
+                validation::check_keepalive(&client.name, client.persistent_keepalive?)
+            }))
+            .chain(disabled_behind_nat)
+            .collect()
+    }
+
+    /// Checks that every key, inline or read from a file, is a well-formed WireGuard key
+    pub fn check_key_format(&self) -> Vec<Issue> {
+        let mut loaded = vec![
+            (
+                &self.router.name,
+                "private",
+                self.router.load_private_key().map(Some),
+            ),
+            (
+                &self.router.name,
+                "public",
+                self.router.load_public_key().map(Some),
+            ),
+        ];
+
+        for client in &self.clients {
+            loaded.push((&client.name, "private", client.load_private_key()));
+            loaded.push((&client.name, "public", client.load_public_key().map(Some)));
+        }
+
+        loaded
+            .into_iter()
+            .filter_map(|(name, kind, key)| match key {
+                Ok(key) => {
+                    let e = keys::validate_wg_key(&key?).err()?;
+
+                    Some(Issue::error(format!(
+                        "{}'s {} key is invalid: {}",
+                        name, kind, e
+                    )))
+                }
+                Err(e) => Some(Issue::error(e.to_string())),
+            })
+            .collect()
+    }
+
+    /// Returns the pairs of peers whose router-side allowed IPs overlap, along with the
+    /// overlapping network. IPv4 and IPv6 networks never overlap each other.
+    pub fn check_allowed_ip_overlaps(&self) -> Vec<(String, String, IpNet)> {
+        let mut overlaps = Vec::new();
+
+        for (i, a) in self.clients.iter().enumerate() {
+            for b in &self.clients[i + 1..] {
+                for net_a in a.router_allowed_ips() {
+                    for net_b in b.router_allowed_ips() {
+                        let overlap = if net_a.contains(&net_b) {
+                            net_b
+                        } else if net_b.contains(&net_a) {
+                            net_a
+                        } else {
+                            continue;
+                        };
+
+                        overlaps.push((a.name.clone(), b.name.clone(), overlap));
+                    }
+                }
+            }
+        }
+
+        overlaps
+    }
+
+    /// Checks that every stored public key matches its private key, when the latter is known.
+    /// Fails if the keys cannot be derived, e.g. when `wg` is not available.
+    pub fn check_keys(&self) -> Result<Vec<Issue>, io::Error> {
+        let mut issues = Vec::new();
+
+        // keys which cannot be loaded are reported by check_key_format
+        let keypairs = std::iter::once((
+            &self.router.name,
+            self.router.load_private_key().ok(),
+            self.router.load_public_key().ok(),
+        ))
+        .chain(self.clients.iter().map(|client| {
+            (
+                &client.name,
+                client.load_private_key().ok().flatten(),
+                client.load_public_key().ok(),
+            )
+        }));
+
+        for (name, private_key, public_key) in keypairs {
+            let (private_key, public_key) = match (private_key, public_key) {
+                (Some(private_key), Some(public_key)) => (private_key, public_key),
+                _ => continue,
+            };
+
+            match keys::pubkey_from_privkey(&private_key) {
+                Ok(derived) if derived == public_key => {}
+                Ok(_) => issues.push(Issue::error(format!(
+                    "{}'s public key does not match its private key",
+                    name
+                ))),
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput => issues.push(Issue::error(
+                    format!("{}'s private key is invalid: {}", name, e),
+                )),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(issues)
+    }
+
+    /// Checks that every router endpoint resolves
+    pub fn check_endpoint(&self) -> Vec<Issue> {
+        self.router
+            .endpoints()
+            .into_iter()
+            .filter_map(validation::check_endpoint_resolves)
+            .collect()
+    }
+
+    /// Checks every client's internal address against the router subnet conventions.
+    pub fn check_subnet_alignment(&self) -> Vec<Issue> {
+        self.clients
+            .iter()
+            .flat_map(|client| self.check_peer_alignment(client))
+            .collect()
+    }
+
+    /// Checks a single peer's internal address: the router's own host address is an error,
+    /// while the network and broadcast addresses of the router's IPv4 subnet (e.g. `.0` and
+    /// `.255` in a /24) are warnings, as is an IPv4 address outside of it.
+    pub fn check_peer_alignment(&self, peer: &Peer) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let router_addresses: Vec<IpAddr> = self
+            .router
+            .internal_addresses()
+            .iter()
+            .map(IpNet::addr)
+            .collect();
+
+        for address in peer
+            .internal_addresses()
+            .iter()
+            .filter(|address| router_addresses.contains(address))
+        {
+            issues.push(Issue::error(format!(
+                "client {} uses {}, which is the router's address",
+                peer.name, address
+            )));
+        }
+
+        if let IpAddr::V4(address) = peer.internal_address {
+            match self.router.internal_address {
+                IpNet::V4(subnet) if subnet.contains(&address) => {
+                    let subnet = subnet.trunc();
+
+                    if subnet.prefix_len() < 31
+                        && (address == subnet.network() || address == subnet.broadcast())
+                    {
+                        issues.push(Issue::warning(format!(
+                            "client {} uses {}, which is the network or broadcast address of {}",
+                            peer.name, address, subnet
+                        )));
+                    }
+                }
+                // the conventions of a subnet the address is not part of cannot be checked
+                IpNet::V4(subnet) => issues.push(Issue::warning(format!(
+                    "client {} uses {}, which is outside of the router subnet {}",
+                    peer.name,
+                    address,
+                    subnet.trunc()
+                ))),
+                IpNet::V6(_) => {}
+            }
+        }
+
+        issues
+    }
+
+    /// Checks a peer's listen port, if any. Sharing the router's port is only a problem
+    /// when the peer runs on the router's host, which cannot be known here, so it is a warning.
+    pub fn check_listen_port(&self, peer: &Peer) -> Vec<Issue> {
+        let mut issues = Vec::new();
+
+        match peer.listen_port {
+            Some(0) => issues.push(Issue::error(format!(
+                "client {} has listen port 0, which must be in 1..=65535",
+                peer.name
+            ))),
+            Some(port) if port == self.router.listen_port() => {
+                issues.push(Issue::warning(format!(
+                    "client {} listens on port {}, which collides with the router's port if they share the host",
+                    peer.name, port
+                )))
+            }
+            _ => {}
+        }
+
+        issues
+    }
+
+    /// Renders the complete router `.conf` file: the interface followed by every enabled peer
+    pub fn router_conf_string(&self) -> Result<String, io::Error> {
+        self.router_conf_string_for(None)
+    }
+
+    /// Renders the router `.conf` file, only including the peers carrying `tag`, if any.
+    /// Disabled peers are always left out. Fails if a key file cannot be read.
+    pub fn router_conf_string_for(&self, tag: Option<&str>) -> Result<String, io::Error> {
+        let mut blocks = vec![self.router.interface_str()?];
+
+        for client in self
+            .clients
+            .iter()
+            .filter(|client| client.enabled)
+            .filter(|client| tag.map_or(true, |tag| client.has_tag(tag)))
+        {
+            blocks.push(self.router.peer_str(client)?);
+        }
+
+        Ok(format!("{}\n", self.with_header(blocks.join("\n\n"))))
+    }
+
+    /// Prepends to a rendered .conf file the comment lines naming the tool version, the date
+    /// and the configuration, if `verbose_comments` is set. wg-quick ignores them.
+    fn with_header(&self, conf: String) -> String {
+        if !self.verbose_comments {
+            return conf;
+        }
 
-        client
-            .interface_str()
-            .map(|interface| format!("{}\n\n{}", interface, client.peer_str(&self.router)))
+        let mut lines = vec![
+            format!(
+                "# Generated by wireguard-configure {}",
+                env!("CARGO_PKG_VERSION")
+            ),
+            format!(
+                "# Generated at {}",
+                Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+            ),
+        ];
+
+        // Configuration, if any
+        if let Some(name) = self
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.name.as_ref())
+        {
+            lines.push(format!("# Configuration {}", name));
+        }
+
+        format!("{}\n\n{}", lines.join("\n"), conf)
+    }
+
+    /// Serializes the configuration as YAML, replacing private keys with a placeholder unless
+    /// `reveal_secrets` is set. Fields are written in declaration order and clients in list
+    /// order, so saving an unchanged configuration gives the same bytes.
+    pub fn to_yaml(&self, reveal_secrets: bool) -> String {
+        self.to_format(ConfigFormat::Yaml, reveal_secrets)
+    }
+
+    /// Serializes the configuration like `to_yaml`, in `format`
+    pub fn to_format(&self, format: ConfigFormat, reveal_secrets: bool) -> String {
+        let serialize = |config: &Configuration| {
+            let text = match format {
+                ConfigFormat::Yaml => serde_yaml::to_string(config)
+                    .expect("Failed to serialize configuration.")
+                    // the document marker depends on the serde_yaml version
+                    .trim_start_matches("---\n")
+                    .to_string(),
+                ConfigFormat::Toml => {
+                    toml::to_string(config).expect("Failed to serialize configuration.")
+                }
+                ConfigFormat::Json => serde_json::to_string_pretty(config)
+                    .expect("Failed to serialize configuration."),
+            };
+
+            // so does the final newline
+            format!("{}\n", text.trim_end_matches('\n'))
+        };
+
+        if reveal_secrets {
+            return serialize(self);
+        }
+
+        let mut redacted = self.clone();
+
+        if redacted.router.private_key.is_some() {
+            redacted.router.private_key = Some(REDACTED_KEY.to_string());
+        }
+
+        for client in &mut redacted.clients {
+            if client.private_key.is_some() {
+                client.set_private_key(Some(REDACTED_KEY.to_string()));
+            }
+        }
+
+        serialize(&redacted)
     }
 
     pub fn is_from_tty(&self) -> bool {
         self.metadata.is_none()
     }
+
+    /// Whether saving prints the configuration to stdout rather than writing its file, when
+    /// it was piped or on a dry run
+    pub fn writes_to_stdout(&self) -> bool {
+        self.is_from_tty() || self.dry_run
+    }
+}
+
+/// Writes `contents` to `path`, making the file readable by its owner only
+pub fn write_private_file(path: &Path, contents: &str) -> Result<(), io::Error> {
+    let mut options = OpenOptions::new();
+
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+
+    // the creation mode does not apply to files which already exist
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+
+    file.write_all(contents.as_bytes())
+}
+
+/// Replaces the file at `path` (the target of a symlink) by one holding `contents`, readable
+/// by its owner only. The file is written aside and renamed over the previous one, so that it
+/// is never left half-written.
+fn replace_private_file(path: &Path, contents: &str) -> Result<(), io::Error> {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        file.as_file()
+            .set_permissions(std::fs::Permissions::from_mode(0o600))?;
+
+        // keeping the owner of the previous file, which only root may give the new one to
+        if let Ok(previous) = std::fs::metadata(&path) {
+            let current = file.as_file().metadata()?;
+
+            if (previous.uid(), previous.gid()) != (current.uid(), current.gid()) {
+                let _ = std::os::unix::fs::fchown(
+                    file.as_file(),
+                    Some(previous.uid()),
+                    Some(previous.gid()),
+                );
+            }
+        }
+    }
+
+    file.write_all(contents.as_bytes())?;
+    file.as_file().sync_all()?;
+    file.persist(&path).map_err(|e| e.error)?;
+
+    Ok(())
+}
+
+/// An address of `subnet` neither in `used` nor in a `reserved` network: the first one in an
+/// IPv4 subnet, the one following the highest address in use in an IPv6 subnet
+fn next_available_in(subnet: IpNet, used: &[IpAddr], reserved: &[IpNet]) -> Option<IpAddr> {
+    let is_free = |address: &IpAddr| {
+        !used.contains(address) && !reserved.iter().any(|net| net.contains(address))
+    };
+
+    match subnet {
+        IpNet::V4(subnet) => subnet.hosts().map(IpAddr::V4).find(is_free),
+        IpNet::V6(subnet) => {
+            // a /64 cannot be enumerated, continue from a cursor on the addresses in use
+            let cursor = used
+                .iter()
+                .filter_map(|address| match address {
+                    IpAddr::V6(address) if subnet.contains(address) => Some(*address),
+                    _ => None,
+                })
+                .max()
+                .unwrap_or_else(|| subnet.network());
+            let mut next = u128::from(cursor).checked_add(1).map(Ipv6Addr::from);
+
+            // jumping over reserved networks, which can be too large to step through
+            while let Some(address) = next.filter(|next| subnet.contains(next)) {
+                if is_free(&IpAddr::V6(address)) {
+                    return Some(IpAddr::V6(address));
+                }
+
+                let last = match reserved
+                    .iter()
+                    .find(|net| net.contains(&IpAddr::V6(address)))
+                {
+                    Some(IpNet::V6(net)) => net.broadcast(),
+                    _ => address,
+                };
+
+                next = u128::from(last).checked_add(1).map(Ipv6Addr::from);
+            }
+
+            // past the end of the subnet, fall back on the first gap, skipping the
+            // subnet-router anycast address
+            subnet.hosts().skip(1).map(IpAddr::V6).find(is_free)
+        }
+    }
+}
+
+/// Number of addresses `IpNet::hosts` yields, without iterating over them: IPv4 subnets
+/// lose their network and broadcast addresses, except for /31 and /32
+fn host_count(net: &IpNet) -> u128 {
+    let bits = u32::from(net.max_prefix_len() - net.prefix_len());
+    let size = 1u128.checked_shl(bits).unwrap_or(u128::MAX);
+
+    match net {
+        IpNet::V4(_) if bits >= 2 => size - 2,
+        _ => size,
+    }
+}
+
+/// Whether `address` is one of the addresses `IpNet::hosts` yields
+fn is_host(net: &IpNet, address: &IpAddr) -> bool {
+    match net {
+        IpNet::V4(v4) if v4.prefix_len() < 31 => {
+            net.contains(address)
+                && *address != IpAddr::V4(v4.network())
+                && *address != IpAddr::V4(v4.broadcast())
+        }
+        _ => net.contains(address),
+    }
+}
+
+/// A node of a mesh, as seen by the other nodes
+struct MeshNode<'a> {
+    name: &'a str,
+    public_key: String,
+    endpoint: Option<&'a AddrPort>,
+    /// The node's own addresses
+    allowed_ips: Vec<IpNet>,
+}
+
+impl MeshNode<'_> {
+    fn peer_str(&self, keepalive: Option<usize>) -> String {
+        let mut lines: Vec<String> = Vec::new();
+
+        // Node name
+        lines.push(format!("# {}", self.name));
+
+        // Peer section begins
+        lines.push("[Peer]".to_string());
+
+        // Public key
+        lines.push(format!("PublicKey = {}", self.public_key));
+
+        // Endpoint, if any, nodes without one wait for the others to reach them
+        if let Some(endpoint) = self.endpoint {
+            lines.push(format!("Endpoint = {}", endpoint));
+        }
+
+        // Keepalive, if any
+        if let Some(keepalive) = keepalive {
+            lines.push(format!("PersistentKeepalive = {}", keepalive));
+        }
+
+        // Allowed IPs
+        lines.push(format!(
+            "AllowedIPs = {}",
+            self.allowed_ips
+                .iter()
+                .map(|ip| format!("{}", ip))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ));
+
+        lines.join("\n")
+    }
+}
+
+/// A section of a wg-quick configuration
+struct WgSection {
+    /// Section type, i.e. `Interface` or `Peer`
+    kind: String,
+    /// Text of the comment line preceding the section, if any
+    name: Option<String>,
+    entries: Vec<(String, String)>,
+}
+
+impl WgSection {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn required(&self, key: &str) -> Result<&str, String> {
+        self.get(key)
+            .ok_or_else(|| format!("Missing {} in [{}] section.", key, self.kind))
+    }
+}
+
+fn parse_wg_sections(text: &str) -> Result<Vec<WgSection>, String> {
+    let mut sections: Vec<WgSection> = Vec::new();
+    let mut last_comment: Option<String> = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(comment) = line.strip_prefix('#') {
+            last_comment = Some(comment.trim().to_string());
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            sections.push(WgSection {
+                kind: line[1..line.len() - 1].trim().to_string(),
+                name: last_comment.take().filter(|name| !name.is_empty()),
+                entries: Vec::new(),
+            });
+            continue;
+        }
+
+        last_comment = None;
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid line {}: {}", number + 1, line))?;
+        let section = sections
+            .last_mut()
+            .ok_or_else(|| format!("Line {} is outside of any section.", number + 1))?;
+
+        section
+            .entries
+            .push((key.trim().to_string(), value.trim().to_string()));
+    }
+
+    Ok(sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endpoint::TableType;
+
+    const ROUTER_PRIVATE_KEY: &str = "cm91dGVyLXByaXZhdGUta2V5LTAwMDAwMDAwMDAwMDA=";
+    const ROUTER_PUBLIC_KEY: &str = "cm91dGVyLXB1YmxpYy1rZXktMDAwMDAwMDAwMDAwMDA=";
+    const CLIENT_PUBLIC_KEYS: [&str; 2] = [
+        "Y2xpZW50LWEtcHVibGljLWtleS0wMDAwMDAwMDAwMDA=",
+        "Y2xpZW50LWItcHVibGljLWtleS0wMDAwMDAwMDAwMDA=",
+    ];
+
+    /// A router at 10.0.1.1/24 and two clients, client-a and client-b, built without `wg`
+    fn sample() -> Configuration {
+        let router = Router::from_keys(
+            "vpn",
+            "10.0.1.1/24".parse().unwrap(),
+            AddrPort::new("vpn.example.com", 51820),
+            ROUTER_PRIVATE_KEY.to_string(),
+            ROUTER_PUBLIC_KEY.to_string(),
+        );
+        let mut config = Configuration::new(router);
+
+        for (i, (name, public_key)) in ["client-a", "client-b"]
+            .iter()
+            .zip(CLIENT_PUBLIC_KEYS)
+            .enumerate()
+        {
+            let address = IpAddr::from([10, 0, 1, 2 + i as u8]);
+
+            config.push_peer(
+                Peer::from_keys(*name, address, None, public_key.to_string())
+                    .with_allowed_ips("10.0.1.0/24".parse().unwrap()),
+            );
+        }
+
+        config
+    }
+
+    /// Writes `text` to a private file of a new directory, returning both
+    fn write_config(name: &str, text: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+
+        write_private_file(&path, text).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn unknown_fields_survive_a_load_and_save() {
+        let text = sample()
+            .to_yaml(true)
+            .replacen("router:\n", "router:\n  future_router_field: kept\n", 1)
+            .replacen(
+                "  - name: client-a\n",
+                "  - name: client-a\n    future_client_field:\n      - 42\n",
+                1,
+            );
+        let (_dir, path) = write_config("wg0.yaml", &text);
+
+        Configuration::from_path(&path).unwrap().save().unwrap();
+
+        let saved = Configuration::from_path(&path).unwrap();
+        let router_field = saved.router.extra.get(&"future_router_field".into());
+        let client_field = saved.clients[0].extra.get(&"future_client_field".into());
+
+        assert_eq!(router_field, Some(&serde_yaml::Value::from("kept")));
+        assert_eq!(
+            client_field,
+            Some(&serde_yaml::Value::Sequence(vec![42.into()]))
+        );
+        assert!(saved.clients[1].extra.is_empty());
+    }
+
+    #[test]
+    fn serializing_is_deterministic() {
+        for format in [ConfigFormat::Yaml, ConfigFormat::Toml, ConfigFormat::Json] {
+            let config = sample();
+            let first = config.to_format(format, true);
+
+            assert_eq!(first, config.to_format(format, true));
+            assert_eq!(first, sample().to_format(format, true));
+
+            // nor does a load and save change anything
+            let reloaded = Configuration::from_str_as(&first, format).unwrap();
+            assert_eq!(reloaded.to_format(format, true), first);
+        }
+    }
+
+    #[test]
+    fn saving_keeps_the_client_order() {
+        let mut config = sample();
+        config.push_peer(Peer::from_keys(
+            "aaa-last",
+            IpAddr::from([10, 0, 1, 9]),
+            None,
+            CLIENT_PUBLIC_KEYS[0].to_string(),
+        ));
+        config.clients.swap(0, 1);
+
+        let reloaded =
+            Configuration::from_str_as(&config.to_yaml(true), ConfigFormat::Yaml).unwrap();
+        let names: Vec<&str> = reloaded
+            .clients
+            .iter()
+            .map(|client| client.name.as_str())
+            .collect();
+
+        assert_eq!(names, ["client-b", "client-a", "aaa-last"]);
+    }
+
+    #[test]
+    fn piped_configurations_are_read_in_either_format() {
+        let config = sample();
+
+        for format in [ConfigFormat::Toml, ConfigFormat::Yaml] {
+            let text = config.to_format(format, true);
+            let piped = Configuration::from_str_any(&text).unwrap();
+
+            assert_eq!(piped.router.name, "vpn");
+            assert_eq!(piped.clients.len(), 2);
+            // it is written back in the format it was read in
+            assert_eq!(piped.format, format);
+            assert_eq!(piped.to_format(piped.format, true), text);
+        }
+    }
+
+    #[test]
+    fn piped_configurations_report_both_errors() {
+        match Configuration::from_str_any("router: [unclosed") {
+            Err(ConfigError::UnknownFormat { toml, yaml }) => {
+                assert!(!toml.is_empty());
+                assert!(!yaml.is_empty());
+            }
+            other => panic!("unexpected {:?}", other.map(|config| config.router.name)),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_restricts_the_file_to_its_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (dir, path) = write_config("wg0.yaml", &sample().to_yaml(true));
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let mut config = Configuration::from_path(&path).unwrap();
+        config.router.mtu = Some(1420);
+        config.save().unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(
+            Configuration::from_path(&path).unwrap().router.mtu,
+            Some(1420)
+        );
+
+        // through a symlink, the target is replaced and the link kept
+        let link = dir.path().join("link.yaml");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        Configuration::from_path(&link).unwrap().save().unwrap();
+
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn tables_round_trip_through_json() {
+        for table in [
+            TableType::Off,
+            TableType::Auto,
+            TableType::Custom(0),
+            TableType::Custom(51820),
+        ] {
+            let mut config = sample();
+            config.router.table = Some(table.clone());
+            config.clients[0].table = Some(table.clone());
+
+            let json = config.to_format(ConfigFormat::Json, true);
+            let parsed = Configuration::from_str_as(&json, ConfigFormat::Json).unwrap();
+
+            assert_eq!(parsed.router.table.as_ref(), Some(&table));
+            assert_eq!(parsed.clients[0].table.as_ref(), Some(&table));
+            assert_eq!(parsed.to_format(ConfigFormat::Json, true), json);
+        }
+    }
+
+    #[test]
+    fn json_files_are_saved_as_json() {
+        let mut config = sample();
+        config.router.table = Some(TableType::Custom(1234));
+
+        let (_dir, path) = write_config("wg0.json", &config.to_format(ConfigFormat::Json, true));
+        let mut loaded = Configuration::from_path(&path).unwrap();
+        assert_eq!(loaded.format, ConfigFormat::Json);
+
+        loaded.router.mtu = Some(1420);
+        loaded.save().unwrap();
+
+        let saved: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["router"]["mtu"], 1420);
+        assert_eq!(saved["router"]["table"], "1234");
+    }
+
+    /// The issues `check_peer_alignment` finds for a client at `address` of `sample()`
+    fn alignment_issues(address: [u8; 4]) -> Vec<Issue> {
+        let peer = Peer::from_keys(
+            "client-c",
+            IpAddr::from(address),
+            None,
+            CLIENT_PUBLIC_KEYS[0].to_string(),
+        );
+
+        sample().check_peer_alignment(&peer)
+    }
+
+    #[test]
+    fn aligned_clients_pass() {
+        assert!(alignment_issues([10, 0, 1, 4]).is_empty());
+        assert!(alignment_issues([10, 0, 1, 254]).is_empty());
+    }
+
+    #[test]
+    fn a_client_at_the_router_address_is_an_error() {
+        let issues = alignment_issues([10, 0, 1, 1]);
+
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].is_error());
+        assert!(issues[0].message.contains("router's address"));
+    }
+
+    #[test]
+    fn network_and_broadcast_clients_are_warnings() {
+        for address in [[10, 0, 1, 0], [10, 0, 1, 255]] {
+            let issues = alignment_issues(address);
+
+            assert_eq!(issues.len(), 1);
+            assert!(!issues[0].is_error());
+            assert!(issues[0].message.contains("network or broadcast"));
+        }
+    }
+
+    #[test]
+    fn clients_outside_of_the_subnet_are_reported_as_such() {
+        // 10.0.2.255 would be a broadcast address in a /24, but the subnet is not its own
+        for address in [[10, 0, 2, 255], [192, 168, 1, 0]] {
+            let issues = alignment_issues(address);
+
+            assert_eq!(issues.len(), 1);
+            assert!(!issues[0].is_error());
+            assert!(issues[0]
+                .message
+                .contains("outside of the router subnet 10.0.1.0/24"));
+        }
+    }
+
+    /// The issues `check_peer_membership` finds for a client at `address` and `address_v6` of
+    /// `sample()`, given the IPv6 subnet `subnet_v6`
+    fn membership_issues(
+        address: &str,
+        address_v6: Option<&str>,
+        subnet_v6: Option<&str>,
+    ) -> Vec<Issue> {
+        let mut config = sample();
+        config
+            .router
+            .set_internal_address_v6(subnet_v6.map(|subnet| subnet.parse().unwrap()));
+
+        let peer = Peer::from_keys(
+            "client-c",
+            address.parse().unwrap(),
+            None,
+            CLIENT_PUBLIC_KEYS[0].to_string(),
+        )
+        .with_internal_address_v6(address_v6.map(|address| address.parse().unwrap()));
+
+        config.check_peer_membership(&peer)
+    }
+
+    #[test]
+    fn clients_in_the_subnet_are_members() {
+        assert!(membership_issues("10.0.1.4", None, None).is_empty());
+        assert!(membership_issues("10.0.1.4", Some("fd00::4"), Some("fd00::1/64")).is_empty());
+    }
+
+    #[test]
+    fn ipv4_clients_outside_of_the_subnet_are_errors() {
+        let issues = membership_issues("10.0.2.4", None, None);
+
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].is_error());
+        assert!(issues[0]
+            .message
+            .contains("10.0.2.4 is outside of the router subnet 10.0.1.0/24"));
+    }
+
+    #[test]
+    fn ipv6_clients_outside_of_the_subnet_are_errors() {
+        let issues = membership_issues("10.0.1.4", Some("fd01::4"), Some("fd00::1/64"));
+
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].is_error());
+        assert!(issues[0]
+            .message
+            .contains("fd01::4 is outside of the router subnet fd00::/64"));
+
+        let issues = membership_issues("10.0.1.4", Some("fd00::4"), None);
+
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].is_error());
+        assert!(issues[0].message.contains("the router has no IPv6 subnet"));
+    }
+
+    // the only test touching CONFIG_DIR_ENV, as tests run in parallel in the same process
+    #[test]
+    fn configurations_are_looked_up_in_the_directory_of_the_environment() {
+        let default = Configuration::default_config_dir().join("wg0.toml");
+
+        std::env::set_var(CONFIG_DIR_ENV, "/srv/wireguard");
+        assert_eq!(
+            Configuration::path_for_name("wg0"),
+            PathBuf::from("/srv/wireguard/wg0.toml")
+        );
+
+        // an empty override is no override
+        std::env::set_var(CONFIG_DIR_ENV, "");
+        assert_eq!(Configuration::path_for_name("wg0"), default);
+
+        std::env::remove_var(CONFIG_DIR_ENV);
+        assert_eq!(Configuration::path_for_name("wg0"), default);
+
+        if !cfg!(windows) {
+            assert_eq!(default, PathBuf::from("/etc/wireguard/wg0.toml"));
+        }
+    }
+
+    #[test]
+    fn clients_behind_nat_without_keepalive_are_warned_about() {
+        let mut config = sample();
+
+        // behind NAT, an unset keepalive defaults to NAT_KEEPALIVE
+        config.clients[0].behind_nat = true;
+        config.clients[1].behind_nat = true;
+        config.clients[1].persistent_keepalive = Some(25);
+        assert!(config.check_keepalives().is_empty());
+
+        config.clients[0].persistent_keepalive = Some(0);
+        let issues = config.check_keepalives();
+
+        assert_eq!(issues.len(), 1);
+        assert!(!issues[0].is_error());
+        assert!(issues[0]
+            .message
+            .contains("client client-a is behind NAT but has no persistent keepalive"));
+
+        // disabling the keepalive of a client reachable from the router is fine
+        config.clients[0].behind_nat = false;
+        assert!(config.check_keepalives().is_empty());
+    }
+
+    #[test]
+    fn saving_a_shorter_configuration_replaces_the_whole_file() {
+        let (_dir, path) = write_config("wg0.yaml", &sample().to_yaml(true));
+
+        let mut config = Configuration::from_path(&path).unwrap();
+        config.clients.truncate(1);
+        config.save().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            config.to_yaml(true)
+        );
+        assert_eq!(Configuration::from_path(&path).unwrap().clients.len(), 1);
+
+        // a private file written over a longer one is truncated as well
+        let longer = sample().to_yaml(true);
+        let shorter = config.to_yaml(true);
+        write_private_file(&path, &longer).unwrap();
+        write_private_file(&path, &shorter).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), shorter);
+    }
+
+    #[test]
+    fn the_router_conf_is_its_interface_and_a_peer_per_client() {
+        let config = sample();
+        let expected = format!(
+            "{}\n\n{}\n\n{}\n",
+            config.router.interface_str().unwrap(),
+            config.router.peer_str(&config.clients[0]).unwrap(),
+            config.router.peer_str(&config.clients[1]).unwrap()
+        );
+
+        assert_eq!(config.router_conf_string().unwrap(), expected);
+    }
+
+    #[test]
+    fn clients_without_allowed_ips_are_warned_about() {
+        let mut config = sample();
+        config.clients[1].allowed_ips.clear();
+
+        let issues = config.check_allowed_ips();
+
+        assert_eq!(issues.len(), 1);
+        assert!(!issues[0].is_error());
+        assert!(issues[0].message.contains(
+            "client client-b has no allowed IPs, allow at least the router address 10.0.1.1/32"
+        ));
+    }
+
+    #[test]
+    fn expiries_are_stored_as_rfc_3339_dates() {
+        let mut config = sample();
+        config.clients[0].expires_at = Some("2024-05-01T12:00:00Z".parse().unwrap());
+
+        let yaml = config.to_yaml(true);
+        assert!(
+            yaml.contains("expires_at: \"2024-05-01T12:00:00Z\""),
+            "{}",
+            yaml
+        );
+
+        for format in [ConfigFormat::Yaml, ConfigFormat::Toml, ConfigFormat::Json] {
+            let reloaded =
+                Configuration::from_str_as(&config.to_format(format, true), format).unwrap();
+
+            assert_eq!(reloaded.clients[0].expires_at, config.clients[0].expires_at);
+            assert!(reloaded.clients[0].is_expired());
+            assert!(!reloaded.clients[1].is_expired());
+        }
+    }
+
+    #[test]
+    fn toml_files_are_read_as_toml_and_legacy_yaml_ones_as_yaml() {
+        assert_eq!(
+            ConfigFormat::for_path(Path::new("wg0.toml")),
+            ConfigFormat::Toml
+        );
+
+        for format in [ConfigFormat::Toml, ConfigFormat::Yaml] {
+            let text = sample().to_format(format, true);
+            let (_dir, path) = write_config("wg0.toml", &text);
+            let loaded = Configuration::from_path(&path).unwrap();
+
+            assert_eq!(loaded.format, format);
+            assert_eq!(loaded.to_format(format, true), text);
+        }
+    }
+
+    #[test]
+    fn files_are_read_in_the_format_given_whatever_their_extension() {
+        let text = sample().to_format(ConfigFormat::Json, true);
+        let (_dir, path) = write_config("wg0.yaml", &text);
+        let loaded = Configuration::from_path_as(&path, ConfigFormat::Json).unwrap();
+
+        assert_eq!(loaded.format, ConfigFormat::Json);
+        assert_eq!(loaded.to_format(ConfigFormat::Json, true), text);
+    }
 }
@@ -1,20 +1,47 @@
 use crate::addrport::AddrPort;
+use crate::keys;
+use chrono::{DateTime, Utc};
 use ipnet::IpNet;
 use serde::de::Visitor;
 use serde::Deserialize;
 use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use serde_yaml::Mapping;
+use std::convert::TryFrom;
 use std::fmt::Display;
-use std::io::Write;
+use std::fs;
+use std::io;
 use std::net::IpAddr;
-use std::process::{Command, Stdio};
-
-#[derive(Clone, Debug, Serialize)]
+use std::num::ParseIntError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Persistent keepalive of peers behind NAT without one, in seconds
+pub const NAT_KEEPALIVE: usize = 25;
+/// Placeholder replacing private keys in redacted output, never a usable key
+pub const REDACTED_KEY: &str = "<redacted>";
+
+/// The `Table` directive of an `[Interface]`. It has no meaning in `[Peer]` sections, which
+/// never carry it.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum TableType {
     Off,
     Auto,
+    /// A routing table number. `0` is kept as a number rather than read as `off`.
     Custom(u32),
 }
 
+impl Serialize for TableType {
+    /// Serializes as the string written in the `Table` directive, so it reads back the same
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
 impl<'de> Deserialize<'de> for TableType {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -30,19 +57,36 @@ impl<'de> Deserialize<'de> for TableType {
             }
 
             fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
-                match v.to_lowercase().as_str() {
-                    "off" => Ok(TableType::Off),
-                    "auto" => Ok(TableType::Auto),
-                    x => {
-                        let value = x.parse().map_err(serde::de::Error::custom)?;
-
-                        Ok(TableType::Custom(value))
-                    }
-                }
+                v.parse().map_err(serde::de::Error::custom)
+            }
+
+            // a bare number, e.g. `table: 51820`
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                u32::try_from(v)
+                    .map(TableType::Custom)
+                    .map_err(|_| E::custom(format!("{} is not a routing table number", v)))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                u32::try_from(v)
+                    .map(TableType::Custom)
+                    .map_err(|_| E::custom(format!("{} is not a routing table number", v)))
             }
         }
 
-        deserializer.deserialize_string(TableTypeVisitor)
+        deserializer.deserialize_any(TableTypeVisitor)
+    }
+}
+
+impl FromStr for TableType {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "off" => Ok(TableType::Off),
+            "auto" => Ok(TableType::Auto),
+            x => Ok(TableType::Custom(x.parse()?)),
+        }
     }
 }
 
@@ -58,51 +102,200 @@ impl Display for TableType {
     }
 }
 
-fn gen_keys() -> Result<(String, String), std::io::Error> {
-    let output = Command::new("wg").args(&["genkey"]).output()?;
+/// Deserializes either a single value or a list of values, treating a missing value as empty
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    let values = match Option::<OneOrMany<T>>::deserialize(deserializer)? {
+        None => Vec::new(),
+        Some(OneOrMany::One(value)) => vec![value],
+        Some(OneOrMany::Many(values)) => values,
+    };
+
+    Ok(values)
+}
+
+/// Removes the repeated allowed IPs and, if `merge`, the ones within a larger allowed network,
+/// keeping the order of the others
+pub fn dedup_allowed_ips(allowed_ips: &[IpNet], merge: bool) -> Vec<IpNet> {
+    let mut kept: Vec<IpNet> = Vec::with_capacity(allowed_ips.len());
+
+    for net in allowed_ips {
+        if !kept.contains(net) {
+            kept.push(*net);
+        }
+    }
+
+    if merge {
+        let unique = kept.clone();
+
+        kept.retain(|net| {
+            !unique.iter().any(|other| {
+                other.prefix_len() < net.prefix_len() && other.trunc().contains(&net.trunc())
+            })
+        });
+    }
+
+    kept
+}
+
+/// The smallest set of networks covering `base` but none of the addresses in `exclude`,
+/// splitting the networks partly excluded in halves until they are either wholly excluded or
+/// not at all
+pub fn subtract_cidrs(base: &[IpNet], exclude: &[IpNet]) -> Vec<IpNet> {
+    let overlaps = |a: &IpNet, b: &IpNet| a.contains(&b.network()) || b.contains(&a.network());
+    let exclude: Vec<IpNet> = exclude.iter().map(IpNet::trunc).collect();
+    let mut remaining = Vec::new();
+    let mut pending: Vec<IpNet> = base.iter().rev().map(IpNet::trunc).collect();
+
+    while let Some(net) = pending.pop() {
+        if exclude.iter().any(|excluded| excluded.contains(&net)) {
+            continue;
+        }
+
+        if !exclude.iter().any(|excluded| overlaps(&net, excluded)) {
+            remaining.push(net);
+            continue;
+        }
+
+        // partly excluded, hence not a single address: its halves are checked in order
+        if let Ok(halves) = net.subnets(net.prefix_len() + 1) {
+            let halves: Vec<IpNet> = halves.collect();
+            pending.extend(halves.into_iter().rev());
+        }
+    }
+
+    remaining
+}
+
+fn default_enabled() -> bool {
+    true
+}
 
-    let privkey = String::from_utf8(output.stdout)
-        .unwrap()
-        .trim()
-        .trim_start()
-        .to_string();
+/// Resolves a key given either inline or through a file holding it. Giving both is refused
+/// as it is ambiguous.
+fn resolve_key(
+    owner: &str,
+    kind: &str,
+    inline: Option<&String>,
+    file: Option<&PathBuf>,
+) -> Result<Option<String>, io::Error> {
+    match (inline, file) {
+        (Some(_), Some(_)) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} has both an inline {} key and a {} key file",
+                owner, kind, kind
+            ),
+        )),
+        (Some(key), None) if key == REDACTED_KEY => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}'s {} key is redacted", owner, kind),
+        )),
+        (Some(key), None) => Ok(Some(key.clone())),
+        (None, Some(path)) => read_key_file(path).map(Some).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "cannot read {}'s {} key file {}: {}",
+                    owner,
+                    kind,
+                    path.display(),
+                    e
+                ),
+            )
+        }),
+        (None, None) => Ok(None),
+    }
+}
 
-    let mut command = Command::new("wg")
-        .args(&["pubkey"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?;
+/// Reads a key stored alone in a file, as written by `wg genkey > file`
+pub fn read_key_file(path: &Path) -> Result<String, io::Error> {
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
 
-    command
-        .stdin
-        .as_mut()
-        .expect("Failed to get stdin for wg pubkey")
-        .write_all(privkey.as_bytes())?;
+/// Arguments to `wg` adding (or updating) `peer` on the live `interface`
+pub fn wg_set_args(interface: &str, peer: &Peer) -> Result<Vec<String>, io::Error> {
+    let mut args = vec![
+        "set".to_string(),
+        interface.to_string(),
+        "peer".to_string(),
+        peer.load_public_key()?,
+    ];
+
+    // an explicit 0 is kept, turning off the keepalive of a peer already on the interface
+    if let Some(keepalive) = peer.persistent_keepalive.or(peer.keepalive()) {
+        args.push("persistent-keepalive".to_string());
+        args.push(keepalive.to_string());
+    }
 
-    let output = command.wait_with_output()?;
+    args.push("allowed-ips".to_string());
+    args.push(
+        peer.router_allowed_ips()
+            .iter()
+            .map(|ip| format!("{}", ip))
+            .collect::<Vec<String>>()
+            .join(","),
+    );
 
-    let pubkey = String::from_utf8(output.stdout)
-        .unwrap()
-        .trim()
-        .trim_start()
-        .to_string();
+    Ok(args)
+}
 
-    Ok((privkey, pubkey))
+/// Arguments to `wg` removing `peer` from the live `interface`
+pub fn wg_remove_args(interface: &str, peer: &Peer) -> Result<Vec<String>, io::Error> {
+    Ok(vec![
+        "set".to_string(),
+        interface.to_string(),
+        "peer".to_string(),
+        peer.load_public_key()?,
+        "remove".to_string(),
+    ])
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Router {
     pub name: String,
     pub internal_address: IpNet,
+    /// IPv6 subnet of a dual-stack router, whose `internal_address` is the IPv4 one
+    #[serde(default)]
+    pub internal_address_v6: Option<IpNet>,
     pub external_address: AddrPort,
-    pub private_key: String,
+    /// Port the router listens on, when it differs from the port of its external address,
+    /// e.g. behind port forwarding
+    #[serde(default)]
+    pub listen_port: Option<u16>,
+    #[serde(default)]
+    pub alternate_endpoints: Vec<AddrPort>,
+    #[serde(default)]
+    pub private_key: Option<String>,
+    #[serde(default)]
+    pub private_key_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub public_key: String,
+    #[serde(default)]
+    pub public_key_file: Option<PathBuf>,
     pub mtu: Option<u16>,
     pub table: Option<TableType>,
+    #[serde(default)]
+    pub fwmark: Option<u32>,
+    #[serde(default)]
+    pub save_config: Option<bool>,
     pub preup: Option<String>,
     pub postup: Option<String>,
     pub predown: Option<String>,
     pub postdown: Option<String>,
+    /// Fields unknown to this version, kept so they survive a load and save
+    #[serde(flatten)]
+    pub extra: Mapping,
 }
 
 impl Router {
@@ -110,22 +303,47 @@ impl Router {
         name: S,
         internal_address: IpNet,
         external_address: AddrPort,
-    ) -> Router {
+    ) -> Result<Router, io::Error> {
         // generating keypair by calling wg on the host system
-        let (private_key, public_key) = gen_keys().expect("Error while generating key pair.");
+        let (private_key, public_key) = keys::gen_keys()?;
+
+        Ok(Router::from_keys(
+            name,
+            internal_address,
+            external_address,
+            private_key,
+            public_key,
+        ))
+    }
 
+    /// Creates a router using the given keypair instead of generating one
+    pub fn from_keys<S: Into<String>>(
+        name: S,
+        internal_address: IpNet,
+        external_address: AddrPort,
+        private_key: String,
+        public_key: String,
+    ) -> Router {
         Router {
             name: name.into(),
-            private_key,
+            private_key: Some(private_key),
+            private_key_file: None,
             public_key,
+            public_key_file: None,
             external_address,
+            listen_port: None,
+            alternate_endpoints: Vec::new(),
             internal_address,
+            internal_address_v6: None,
             mtu: None,
             table: None,
+            fwmark: None,
+            save_config: None,
             preup: None,
             postup: None,
             predown: None,
             postdown: None,
+            extra: Mapping::new(),
         }
     }
 
@@ -133,6 +351,11 @@ impl Router {
      * Builder functions
      */
 
+    pub fn with_internal_address_v6(mut self, internal_address_v6: Option<IpNet>) -> Router {
+        self.internal_address_v6 = internal_address_v6;
+        self
+    }
+
     pub fn with_mtu(mut self, mtu: Option<u16>) -> Router {
         self.mtu = mtu;
         self
@@ -143,6 +366,21 @@ impl Router {
         self
     }
 
+    pub fn with_fwmark(mut self, fwmark: Option<u32>) -> Router {
+        self.fwmark = fwmark;
+        self
+    }
+
+    pub fn with_save_config(mut self, save_config: Option<bool>) -> Router {
+        self.save_config = save_config;
+        self
+    }
+
+    pub fn with_listen_port(mut self, listen_port: Option<u16>) -> Router {
+        self.listen_port = listen_port;
+        self
+    }
+
     pub fn with_preup(mut self, preup: Option<String>) -> Router {
         self.preup = preup;
         self
@@ -171,15 +409,147 @@ impl Router {
         self.external_address = external_address;
     }
 
+    pub fn push_alternate_endpoint(&mut self, endpoint: AddrPort) {
+        self.alternate_endpoints.push(endpoint);
+    }
+
+    pub fn clear_alternate_endpoints(&mut self) {
+        self.alternate_endpoints.clear();
+    }
+
     pub fn set_internal_address(&mut self, internal_address: IpNet) {
         self.internal_address = internal_address;
     }
 
+    pub fn set_internal_address_v6(&mut self, internal_address_v6: Option<IpNet>) {
+        self.internal_address_v6 = internal_address_v6;
+    }
+
+    pub fn set_mtu(&mut self, mtu: Option<u16>) {
+        self.mtu = mtu;
+    }
+
+    pub fn set_table(&mut self, table: Option<TableType>) {
+        self.table = table;
+    }
+
+    pub fn set_fwmark(&mut self, fwmark: Option<u32>) {
+        self.fwmark = fwmark;
+    }
+
+    pub fn set_preup(&mut self, preup: Option<String>) {
+        self.preup = preup;
+    }
+
+    pub fn set_postup(&mut self, postup: Option<String>) {
+        self.postup = postup;
+    }
+
+    pub fn set_predown(&mut self, predown: Option<String>) {
+        self.predown = predown;
+    }
+
+    pub fn set_postdown(&mut self, postdown: Option<String>) {
+        self.postdown = postdown;
+    }
+
+    pub fn set_save_config(&mut self, save_config: Option<bool>) {
+        self.save_config = save_config;
+    }
+
+    pub fn set_listen_port(&mut self, listen_port: Option<u16>) {
+        self.listen_port = listen_port;
+    }
+
     /*
      *
      */
 
-    pub fn interface_str(&self) -> String {
+    /// Replaces the keypair with a freshly generated one. Keys read from files are managed
+    /// externally and cannot be rotated.
+    pub fn rotate_keys(&mut self) -> Result<(), io::Error> {
+        if self.private_key_file.is_some() || self.public_key_file.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the keys of {} are read from files", self.name),
+            ));
+        }
+
+        let (private_key, public_key) = keys::gen_keys()?;
+
+        self.private_key = Some(private_key);
+        self.public_key = public_key;
+
+        Ok(())
+    }
+
+    /// The router's addresses, the IPv6 one of a dual-stack router last
+    pub fn internal_addresses(&self) -> Vec<IpNet> {
+        std::iter::once(self.internal_address)
+            .chain(self.internal_address_v6)
+            .collect()
+    }
+
+    /// Whether the router has an IPv6 subnet, its MTU then being at least `MIN_IPV6_MTU`
+    pub fn has_ipv6(&self) -> bool {
+        self.internal_addresses()
+            .iter()
+            .any(|net| matches!(net, IpNet::V6(_)))
+    }
+
+    /// The port the router listens on, the one of its external address unless set apart
+    pub fn listen_port(&self) -> u16 {
+        self.listen_port.unwrap_or(self.external_address.port)
+    }
+
+    /// The router's subnets, the IPv6 one of a dual-stack router last
+    pub fn subnets(&self) -> Vec<IpNet> {
+        self.internal_addresses().iter().map(IpNet::trunc).collect()
+    }
+
+    /// Every endpoint clients can reach the router at, the external address first
+    pub fn endpoints(&self) -> Vec<&AddrPort> {
+        std::iter::once(&self.external_address)
+            .chain(&self.alternate_endpoints)
+            .collect()
+    }
+
+    /// The endpoint at `index` in `endpoints()`, if any
+    pub fn endpoint(&self, index: usize) -> Option<&AddrPort> {
+        self.endpoints().get(index).copied()
+    }
+
+    /// The private key, read from its file if it is not given inline
+    pub fn load_private_key(&self) -> Result<String, io::Error> {
+        resolve_key(
+            &self.name,
+            "private",
+            self.private_key.as_ref(),
+            self.private_key_file.as_ref(),
+        )?
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} has no private key", self.name),
+            )
+        })
+    }
+
+    /// The public key, read from its file if it is not given inline
+    pub fn load_public_key(&self) -> Result<String, io::Error> {
+        let inline = Some(&self.public_key).filter(|key| !key.is_empty());
+
+        resolve_key(&self.name, "public", inline, self.public_key_file.as_ref())?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} has no public key", self.name),
+            )
+        })
+    }
+
+    /// Renders the `[Interface]` section, each key at most once and in the same order as
+    /// for peers, so that wg-quick reads both alike
+    pub fn interface_str(&self) -> Result<String, io::Error> {
         let mut lines: Vec<String> = Vec::new();
 
         // Router name
@@ -188,14 +558,21 @@ impl Router {
         // Interface section begins
         lines.push("[Interface]".to_string());
 
-        // Internal address
-        lines.push(format!("Address = {}", IpNet::from(self.internal_address)));
-
         // Private key
-        lines.push(format!("PrivateKey = {}", self.private_key));
+        lines.push(format!("PrivateKey = {}", self.load_private_key()?));
+
+        // Internal addresses
+        lines.push(format!(
+            "Address = {}",
+            self.internal_addresses()
+                .iter()
+                .map(|address| format!("{}", address))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ));
 
         // Listen port
-        lines.push(format!("ListenPort = {}", self.external_address.port));
+        lines.push(format!("ListenPort = {}", self.listen_port()));
 
         // MTU, if any
         if let Some(mtu) = self.mtu {
@@ -207,6 +584,16 @@ impl Router {
             lines.push(format!("Table = {}", table));
         }
 
+        // FwMark, if any
+        if let Some(fwmark) = self.fwmark {
+            lines.push(format!("FwMark = {:#x}", fwmark));
+        }
+
+        // SaveConfig, if enabled
+        if let Some(true) = self.save_config {
+            lines.push("SaveConfig = true".to_string());
+        }
+
         // PreUp, if any
         if let Some(preup) = &self.preup {
             lines.push(format!("PreUp = {}", preup));
@@ -227,67 +614,155 @@ impl Router {
             lines.push(format!("PostDown = {}", postdown));
         }
 
-        lines.join("\n")
+        Ok(lines.join("\n"))
     }
 
-    pub fn peer_str(&self, peer: &Peer) -> String {
+    pub fn peer_str(&self, peer: &Peer) -> Result<String, io::Error> {
         let mut lines: Vec<String> = Vec::new();
 
         // Peer name
         lines.push(format!("# {}", peer.name));
 
+        // Peer description, if any
+        lines.extend(peer.comment_lines());
+
         // Peer section begins
         lines.push("[Peer]".to_string());
 
         // Public key
-        lines.push(format!("PublicKey = {}", peer.public_key));
+        lines.push(format!("PublicKey = {}", peer.load_public_key()?));
+
+        // Endpoint the router reaches the peer at, if it is reachable
+        if let Some(endpoint) = &peer.mesh_endpoint {
+            lines.push(format!("Endpoint = {}", endpoint));
+        }
+
+        // Persistent keepalive, if any, e.g. for routers behind NAT
+        if let Some(keepalive) = peer.keepalive() {
+            lines.push(format!("PersistentKeepalive = {}", keepalive));
+        }
 
         // Allowed IPs
         lines.push(format!(
             "AllowedIPs = {}",
-            IpNet::from(peer.internal_address)
+            peer.router_allowed_ips()
+                .iter()
+                .map(|ip| format!("{}", ip))
+                .collect::<Vec<String>>()
+                .join(", ")
         ));
 
-        lines.join("\n")
+        Ok(lines.join("\n"))
     }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Peer {
     pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Whether the peer is part of the router configuration, as reported by `list`. Existing
+    /// configurations have no such field and keep every peer enabled.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// When the peer's access ends, for temporary peers
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
     pub internal_address: IpAddr,
+    /// IPv6 address of a dual-stack peer, whose `internal_address` is the IPv4 one
+    #[serde(default)]
+    pub internal_address_v6: Option<IpAddr>,
     pub allowed_ips: Vec<IpNet>,
-    pub dns: Option<IpAddr>,
+    /// Networks behind the peer, e.g. the LAN of a site-to-site gateway, routed to it by the
+    /// router along with its own addresses
+    #[serde(default)]
+    pub routed_subnets: Vec<IpNet>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub dns: Vec<IpAddr>,
+    /// DNS search domains, only used along with DNS servers
+    #[serde(default)]
+    pub dns_search: Vec<String>,
     pub persistent_keepalive: Option<usize>,
+    /// Whether the peer is behind NAT, giving it `NAT_KEEPALIVE` unless a keepalive is set
+    #[serde(default)]
+    pub behind_nat: bool,
+    #[serde(default)]
+    pub listen_port: Option<u16>,
+    #[serde(default)]
+    pub endpoint_override: Option<AddrPort>,
+    /// Where the other nodes of a mesh reach this peer, if it is reachable
+    #[serde(default)]
+    pub mesh_endpoint: Option<AddrPort>,
+    #[serde(default)]
     pub private_key: Option<String>,
+    #[serde(default)]
+    pub private_key_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub public_key: String,
+    #[serde(default)]
+    pub public_key_file: Option<PathBuf>,
     pub mtu: Option<u16>,
     pub table: Option<TableType>,
     pub preup: Option<String>,
     pub postup: Option<String>,
     pub predown: Option<String>,
     pub postdown: Option<String>,
+    /// Fields unknown to this version, kept so they survive a load and save
+    #[serde(flatten)]
+    pub extra: Mapping,
 }
 
 impl Peer {
-    pub fn new<S: Into<String>>(name: S, internal_address: IpAddr) -> Peer {
+    pub fn new<S: Into<String>>(name: S, internal_address: IpAddr) -> Result<Peer, io::Error> {
         // generating keypair by calling wg on the host system
-        let (private_key, public_key) = gen_keys().expect("Error while generating key pair.");
+        let (private_key, public_key) = keys::gen_keys()?;
+
+        Ok(Peer::from_keys(
+            name,
+            internal_address,
+            Some(private_key),
+            public_key,
+        ))
+    }
 
+    /// Creates a peer using the given keys instead of generating a keypair. Without a
+    /// private key, the peer is server-side only.
+    pub fn from_keys<S: Into<String>>(
+        name: S,
+        internal_address: IpAddr,
+        private_key: Option<String>,
+        public_key: String,
+    ) -> Peer {
         Peer {
             name: name.into(),
-            private_key: Some(private_key),
+            description: None,
+            tags: Vec::new(),
+            enabled: true,
+            expires_at: None,
+            private_key,
+            private_key_file: None,
             public_key,
+            public_key_file: None,
             internal_address,
-            dns: None,
+            internal_address_v6: None,
+            dns: Vec::new(),
+            dns_search: Vec::new(),
             allowed_ips: Vec::new(),
+            routed_subnets: Vec::new(),
             persistent_keepalive: None,
+            behind_nat: false,
+            listen_port: None,
+            endpoint_override: None,
+            mesh_endpoint: None,
             mtu: None,
             table: None,
             preup: None,
             postup: None,
             predown: None,
             postdown: None,
+            extra: Mapping::new(),
         }
     }
 
@@ -295,21 +770,71 @@ impl Peer {
     // Builder functions
     //
 
-    pub fn with_dns(mut self, dns: Option<IpAddr>) -> Peer {
+    pub fn with_description(mut self, description: Option<String>) -> Peer {
+        self.description = description;
+        self
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Peer {
+        self.tags = tags;
+        self
+    }
+
+    pub fn with_expires_at(mut self, expires_at: Option<DateTime<Utc>>) -> Peer {
+        self.expires_at = expires_at;
+        self
+    }
+
+    pub fn with_internal_address_v6(mut self, internal_address_v6: Option<IpAddr>) -> Peer {
+        self.internal_address_v6 = internal_address_v6;
+        self
+    }
+
+    pub fn with_dns(mut self, dns: Vec<IpAddr>) -> Peer {
         self.dns = dns;
         self
     }
 
+    pub fn with_dns_search(mut self, dns_search: Vec<String>) -> Peer {
+        self.dns_search = dns_search;
+        self
+    }
+
     pub fn with_keepalive(mut self, keepalive: Option<usize>) -> Peer {
         self.persistent_keepalive = keepalive;
         self
     }
 
+    pub fn with_behind_nat(mut self, behind_nat: bool) -> Peer {
+        self.behind_nat = behind_nat;
+        self
+    }
+
+    pub fn with_listen_port(mut self, listen_port: Option<u16>) -> Peer {
+        self.listen_port = listen_port;
+        self
+    }
+
+    pub fn with_endpoint_override(mut self, endpoint: Option<AddrPort>) -> Peer {
+        self.endpoint_override = endpoint;
+        self
+    }
+
+    pub fn with_mesh_endpoint(mut self, mesh_endpoint: Option<AddrPort>) -> Peer {
+        self.mesh_endpoint = mesh_endpoint;
+        self
+    }
+
     pub fn with_vec_allowed_ips(mut self, allowed_ips: Vec<IpNet>) -> Peer {
         self.allowed_ips = allowed_ips;
         self
     }
 
+    pub fn with_routed_subnets(mut self, routed_subnets: Vec<IpNet>) -> Peer {
+        self.routed_subnets = routed_subnets;
+        self
+    }
+
     pub fn with_allowed_ips(mut self, allowed_ips: IpNet) -> Peer {
         self.allowed_ips.push(allowed_ips);
         self
@@ -325,6 +850,26 @@ impl Peer {
         self
     }
 
+    pub fn with_preup(mut self, preup: Option<String>) -> Peer {
+        self.preup = preup;
+        self
+    }
+
+    pub fn with_postup(mut self, postup: Option<String>) -> Peer {
+        self.postup = postup;
+        self
+    }
+
+    pub fn with_predown(mut self, predown: Option<String>) -> Peer {
+        self.predown = predown;
+        self
+    }
+
+    pub fn with_postdown(mut self, postdown: Option<String>) -> Peer {
+        self.postdown = postdown;
+        self
+    }
+
     //
     // Setters
     //
@@ -333,10 +878,22 @@ impl Peer {
         self.allowed_ips.push(allowed_ips);
     }
 
+    pub fn set_description(&mut self, description: Option<String>) {
+        self.description = description;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
     pub fn set_internal_address(&mut self, internal_address: IpAddr) {
         self.internal_address = internal_address;
     }
 
+    pub fn set_internal_address_v6(&mut self, internal_address_v6: Option<IpAddr>) {
+        self.internal_address_v6 = internal_address_v6;
+    }
+
     pub fn set_persistent_keepalive(&mut self, keepalive: Option<usize>) {
         self.persistent_keepalive = keepalive;
     }
@@ -349,30 +906,159 @@ impl Peer {
         self.public_key = public_key;
     }
 
+    pub fn set_private_key_file(&mut self, private_key_file: Option<PathBuf>) {
+        self.private_key_file = private_key_file;
+    }
+
+    /// Replaces the keypair with a freshly generated one. Keys read from files are managed
+    /// externally and cannot be rotated.
+    pub fn rotate_keys(&mut self) -> Result<(), std::io::Error> {
+        if self.private_key_file.is_some() || self.public_key_file.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the keys of {} are read from files", self.name),
+            ));
+        }
+
+        let (private_key, public_key) = keys::gen_keys()?;
+
+        self.private_key = Some(private_key);
+        self.public_key = public_key;
+
+        Ok(())
+    }
+
     //
     // Other functions
     //
 
-    pub fn interface_str(&self) -> Option<String> {
+    /// The persistent keepalive to write, none when explicitly disabled with 0 or when unset,
+    /// unless the peer is behind NAT
+    pub fn keepalive(&self) -> Option<usize> {
+        match self.persistent_keepalive {
+            Some(keepalive) => Some(keepalive).filter(|keepalive| *keepalive != 0),
+            None if self.behind_nat => Some(NAT_KEEPALIVE),
+            None => None,
+        }
+    }
+
+    /// Whether a full client configuration can be produced for this peer. Peers added with
+    /// an externally supplied public key have no private key and are server-side only.
+    pub fn is_complete(&self) -> bool {
+        self.private_key.is_some() || self.private_key_file.is_some()
+    }
+
+    /// The private key, if any, read from its file if it is not given inline
+    pub fn load_private_key(&self) -> Result<Option<String>, io::Error> {
+        resolve_key(
+            &self.name,
+            "private",
+            self.private_key.as_ref(),
+            self.private_key_file.as_ref(),
+        )
+    }
+
+    /// The public key, read from its file if it is not given inline
+    pub fn load_public_key(&self) -> Result<String, io::Error> {
+        let inline = Some(&self.public_key).filter(|key| !key.is_empty());
+
+        resolve_key(&self.name, "public", inline, self.public_key_file.as_ref())?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} has no public key", self.name),
+            )
+        })
+    }
+
+    /// Whether the peer's access has ended
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// The description as comment lines, one per line of text
+    fn comment_lines(&self) -> Vec<String> {
+        self.description
+            .iter()
+            .flat_map(|description| description.lines())
+            .map(|line| format!("# {}", line))
+            .collect()
+    }
+
+    /// The peer's addresses, the IPv6 one of a dual-stack peer last
+    pub fn internal_addresses(&self) -> Vec<IpAddr> {
+        std::iter::once(self.internal_address)
+            .chain(self.internal_address_v6)
+            .collect()
+    }
+
+    /// Whether the peer has an IPv6 address, its MTU then being at least `MIN_IPV6_MTU`
+    pub fn has_ipv6(&self) -> bool {
+        self.internal_addresses().iter().any(IpAddr::is_ipv6)
+    }
+
+    /// The networks the router routes to this peer: its own addresses, then its routed
+    /// subnets
+    pub fn router_allowed_ips(&self) -> Vec<IpNet> {
+        self.internal_addresses()
+            .into_iter()
+            .map(IpNet::from)
+            .chain(self.routed_subnets.iter().copied())
+            .collect()
+    }
+
+    /// Renders the `[Interface]` section of a peer holding its private key, each key at most
+    /// once and in the same order as for the router. `DNS`, `MTU` and `Table` are only ever
+    /// written here, never in `[Peer]` sections.
+    pub fn interface_str(&self) -> Result<Option<String>, io::Error> {
         let mut lines: Vec<String> = Vec::new();
 
-        match &self.private_key {
+        match self.load_private_key()? {
             Some(private_key) => {
                 // Peer name
                 lines.push(format!("# {}", self.name));
 
+                // Peer description, if any
+                lines.extend(self.comment_lines());
+
                 // Interface section begins
                 lines.push("[Interface]".to_string());
 
                 // Private key
                 lines.push(format!("PrivateKey = {}", private_key));
 
-                // Internal address
-                lines.push(format!("Address = {}", IpNet::from(self.internal_address)));
+                // Internal addresses
+                lines.push(format!(
+                    "Address = {}",
+                    self.internal_addresses()
+                        .into_iter()
+                        .map(IpNet::from)
+                        .map(|address| format!("{}", address))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ));
+
+                // Listen port, if any
+                if let Some(listen_port) = self.listen_port {
+                    lines.push(format!("ListenPort = {}", listen_port));
+                }
 
-                // DNS, if any
-                if let Some(dns) = self.dns {
-                    lines.push(format!("DNS = {}", dns));
+                // DNS, if any, followed by the search domains, which wg-quick only accepts
+                // along with a server
+                if !self.dns.is_empty() {
+                    lines.push(format!(
+                        "DNS = {}",
+                        self.dns
+                            .iter()
+                            .map(|dns| format!("{}", dns))
+                            .chain(self.dns_search.iter().cloned())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ));
                 }
 
                 // MTU, if any
@@ -384,6 +1070,7 @@ impl Peer {
                 if let Some(table) = &self.table {
                     lines.push(format!("Table = {}", table));
                 }
+
                 // PreUp, if any
                 if let Some(preup) = &self.preup {
                     lines.push(format!("PreUp = {}", preup));
@@ -404,14 +1091,30 @@ impl Peer {
                     lines.push(format!("PostDown = {}", postdown));
                 }
 
-                Some(lines.join("\n"))
+                Ok(Some(lines.join("\n")))
             }
             // if no private key is present, we cannot produce a valid Interface section
-            None => None,
+            None => Ok(None),
         }
     }
 
-    pub fn peer_str(&self, router: &Router) -> String {
+    /// Renders the router as seen by this peer, reaching it at the peer's endpoint override,
+    /// if any, or at the router's external address
+    pub fn peer_str(&self, router: &Router) -> Result<String, io::Error> {
+        let endpoint = self
+            .endpoint_override
+            .as_ref()
+            .unwrap_or(&router.external_address);
+
+        self.peer_str_for_endpoint(router, endpoint)
+    }
+
+    /// Renders the router as seen by this peer, reaching it at `endpoint`
+    pub fn peer_str_for_endpoint(
+        &self,
+        router: &Router,
+        endpoint: &AddrPort,
+    ) -> Result<String, io::Error> {
         let mut lines: Vec<String> = Vec::new();
 
         // Router name
@@ -421,29 +1124,391 @@ impl Peer {
         lines.push("[Peer]".to_string());
 
         // Public key
-        lines.push(format!("PublicKey = {}", router.public_key));
+        lines.push(format!("PublicKey = {}", router.load_public_key()?));
 
         // Router endpoint
-        lines.push(format!(
-            "Endpoint = {}:{}",
-            router.external_address.address, router.external_address.port
-        ));
+        lines.push(format!("Endpoint = {}", endpoint));
 
         // Keepalive, if any
-        if let Some(keepalive) = self.persistent_keepalive {
+        if let Some(keepalive) = self.keepalive() {
             lines.push(format!("PersistentKeepalive = {}", keepalive));
         }
 
-        // Allowed IPs
-        lines.push(format!(
-            "AllowedIPs = {}",
-            self.allowed_ips
-                .iter()
-                .map(|ip| format!("{}", ip))
-                .collect::<Vec<String>>()
-                .join(", ")
-        ));
+        // Allowed IPs, if any, see `Configuration::check_peer_allowed_ips`
+        if !self.allowed_ips.is_empty() {
+            lines.push(format!(
+                "AllowedIPs = {}",
+                self.allowed_ips
+                    .iter()
+                    .map(|ip| format!("{}", ip))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ));
+        }
+
+        Ok(lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_KEY: &str = "cm91dGVyLXByaXZhdGUta2V5LTAwMDAwMDAwMDAwMDA=";
+    const PUBLIC_KEY: &str = "cm91dGVyLXB1YmxpYy1rZXktMDAwMDAwMDAwMDAwMDA=";
+
+    /// A dual-stack router setting every `[Interface]` key
+    fn router() -> Router {
+        Router::from_keys(
+            "vpn",
+            "10.0.1.1/24".parse().unwrap(),
+            AddrPort::new("vpn.example.com", 443),
+            PRIVATE_KEY.to_string(),
+            PUBLIC_KEY.to_string(),
+        )
+        .with_internal_address_v6(Some("fd00::1/64".parse().unwrap()))
+        .with_listen_port(Some(51820))
+        .with_mtu(Some(1420))
+        .with_table(Some(TableType::Off))
+        .with_fwmark(Some(51820))
+        .with_save_config(Some(true))
+        .with_preup(Some("true".to_string()))
+        .with_postup(Some("true".to_string()))
+        .with_predown(Some("true".to_string()))
+        .with_postdown(Some("true".to_string()))
+    }
+
+    /// A dual-stack client setting every `[Interface]` key
+    fn client() -> Peer {
+        Peer::from_keys(
+            "client",
+            "10.0.1.2".parse().unwrap(),
+            Some(PRIVATE_KEY.to_string()),
+            PUBLIC_KEY.to_string(),
+        )
+        .with_internal_address_v6(Some("fd00::2".parse().unwrap()))
+        .with_allowed_ips("0.0.0.0/0".parse().unwrap())
+        .with_dns(vec!["10.0.1.1".parse().unwrap()])
+        .with_dns_search(vec!["vpn.internal".to_string()])
+        .with_keepalive(Some(25))
+        .with_listen_port(Some(51821))
+        .with_mtu(Some(1380))
+        .with_table(Some(TableType::Custom(1234)))
+        .with_preup(Some("true".to_string()))
+        .with_postup(Some("true".to_string()))
+        .with_predown(Some("true".to_string()))
+        .with_postdown(Some("true".to_string()))
+    }
+
+    /// Checks a wg-quick configuration the way wg-quick reads it: every line is a comment, a
+    /// section header or a `key = value` of a section, and no key is repeated in a section.
+    /// Returns the sections with their keys.
+    fn parse_wg_quick(conf: &str) -> Vec<(String, Vec<String>)> {
+        let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+
+        for line in conf.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(kind) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                sections.push((kind.to_string(), Vec::new()));
+                continue;
+            }
+
+            let (key, value) = line.split_once(" = ").expect(line);
+            assert!(!value.is_empty(), "{} has no value", key);
+
+            let (kind, keys) = sections.last_mut().expect("key outside of a section");
+            assert!(
+                !keys.iter().any(|k| k == key),
+                "{} repeated in [{}]",
+                key,
+                kind
+            );
+            keys.push(key.to_string());
+        }
+
+        sections
+    }
+
+    fn assert_interface_only_keys_absent(sections: &[(String, Vec<String>)]) {
+        for (kind, keys) in sections.iter().filter(|(kind, _)| kind == "Peer") {
+            for key in ["DNS", "MTU", "Table", "Address", "PrivateKey", "ListenPort"] {
+                assert!(!keys.iter().any(|k| k == key), "{} in [{}]", key, kind);
+            }
+        }
+    }
+
+    #[test]
+    fn router_configuration_is_valid_wg_quick() {
+        let router = router();
+        let conf = format!(
+            "{}\n\n{}\n",
+            router.interface_str().unwrap(),
+            router.peer_str(&client()).unwrap()
+        );
+        let sections = parse_wg_quick(&conf);
+
+        let kinds: Vec<&str> = sections.iter().map(|(kind, _)| kind.as_str()).collect();
+        assert_eq!(kinds, ["Interface", "Peer"]);
+        assert_eq!(
+            sections[0].1,
+            [
+                "PrivateKey",
+                "Address",
+                "ListenPort",
+                "MTU",
+                "Table",
+                "FwMark",
+                "SaveConfig",
+                "PreUp",
+                "PostUp",
+                "PreDown",
+                "PostDown"
+            ]
+        );
+        assert_interface_only_keys_absent(&sections);
+    }
+
+    #[test]
+    fn client_configuration_is_valid_wg_quick() {
+        let client = client();
+        let conf = format!(
+            "{}\n\n{}\n",
+            client.interface_str().unwrap().unwrap(),
+            client.peer_str(&router()).unwrap()
+        );
+        let sections = parse_wg_quick(&conf);
+
+        let kinds: Vec<&str> = sections.iter().map(|(kind, _)| kind.as_str()).collect();
+        assert_eq!(kinds, ["Interface", "Peer"]);
+        assert_eq!(
+            sections[0].1,
+            [
+                "PrivateKey",
+                "Address",
+                "ListenPort",
+                "DNS",
+                "MTU",
+                "Table",
+                "PreUp",
+                "PostUp",
+                "PreDown",
+                "PostDown"
+            ]
+        );
+        assert_interface_only_keys_absent(&sections);
+    }
+
+    fn nets(nets: &[&str]) -> Vec<IpNet> {
+        nets.iter().map(|net| net.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn subtracting_a_lan_from_everything() {
+        let mut remaining = subtract_cidrs(&nets(&["0.0.0.0/0"]), &nets(&["192.168.1.0/24"]));
+        remaining.sort();
+
+        assert_eq!(
+            remaining,
+            nets(&[
+                "0.0.0.0/1",
+                "128.0.0.0/2",
+                "192.0.0.0/9",
+                "192.128.0.0/11",
+                "192.160.0.0/13",
+                "192.168.0.0/24",
+                "192.168.2.0/23",
+                "192.168.4.0/22",
+                "192.168.8.0/21",
+                "192.168.16.0/20",
+                "192.168.32.0/19",
+                "192.168.64.0/18",
+                "192.168.128.0/17",
+                "192.169.0.0/16",
+                "192.170.0.0/15",
+                "192.172.0.0/14",
+                "192.176.0.0/12",
+                "192.192.0.0/10",
+                "193.0.0.0/8",
+                "194.0.0.0/7",
+                "196.0.0.0/6",
+                "200.0.0.0/5",
+                "208.0.0.0/4",
+                "224.0.0.0/3",
+            ])
+        );
+    }
+
+    #[test]
+    fn subtracting_leaves_other_networks_alone() {
+        let base = nets(&["0.0.0.0/0", "::/0"]);
+
+        assert_eq!(subtract_cidrs(&base, &[]), base);
+        assert_eq!(
+            subtract_cidrs(&nets(&["10.0.0.0/8", "::/0"]), &nets(&["192.168.1.0/24"])),
+            nets(&["10.0.0.0/8", "::/0"])
+        );
+        assert!(subtract_cidrs(&nets(&["10.0.1.0/24"]), &nets(&["10.0.0.0/8"])).is_empty());
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Table {
+        table: TableType,
+    }
+
+    #[test]
+    fn tables_deserialize_from_numbers_and_strings() {
+        let yaml = |text: &str| serde_yaml::from_str::<Table>(text).unwrap().table;
+        let json = |text: &str| serde_json::from_str::<Table>(text).unwrap().table;
+
+        assert_eq!(yaml("table: 51820"), TableType::Custom(51820));
+        assert_eq!(yaml("table: \"51820\""), TableType::Custom(51820));
+        assert_eq!(yaml("table: auto"), TableType::Auto);
+        assert_eq!(yaml("table: \"off\""), TableType::Off);
+        assert_eq!(yaml("table: 0"), TableType::Custom(0));
+
+        assert_eq!(json(r#"{"table": 51820}"#), TableType::Custom(51820));
+        assert_eq!(json(r#"{"table": "51820"}"#), TableType::Custom(51820));
+        assert_eq!(json(r#"{"table": "auto"}"#), TableType::Auto);
+    }
+
+    #[test]
+    fn tables_read_back_as_written() {
+        for table in [
+            TableType::Off,
+            TableType::Auto,
+            TableType::Custom(0),
+            TableType::Custom(1234),
+        ] {
+            let written = Table {
+                table: table.clone(),
+            };
+
+            let yaml = serde_yaml::to_string(&written).unwrap();
+            let json = serde_json::to_string(&written).unwrap();
+            let toml = toml::to_string(&written).unwrap();
+
+            assert_eq!(serde_yaml::from_str::<Table>(&yaml).unwrap().table, table);
+            assert_eq!(serde_json::from_str::<Table>(&json).unwrap().table, table);
+            assert_eq!(toml::from_str::<Table>(&toml).unwrap().table, table);
+        }
+    }
+
+    #[test]
+    fn tables_are_rendered_in_the_interface_only() {
+        // 0 stays a number, validation being the one refusing it
+        for (table, rendered) in [
+            (TableType::Off, "off"),
+            (TableType::Auto, "auto"),
+            (TableType::Custom(0), "0"),
+        ] {
+            let client = client().with_table(Some(table));
+            let router = router().with_table(Some(TableType::Off));
+
+            let interface = client.interface_str().unwrap().unwrap();
+            assert!(
+                interface.contains(&format!("Table = {}\n", rendered)),
+                "{}",
+                interface
+            );
+            assert!(!router.peer_str(&client).unwrap().contains("Table"));
+            assert!(!client.peer_str(&router).unwrap().contains("Table"));
+        }
+    }
+
+    #[test]
+    fn tables_refuse_other_values() {
+        for text in [
+            "table: -1",
+            "table: 4294967296",
+            "table: main",
+            "table: 1.5",
+        ] {
+            assert!(serde_yaml::from_str::<Table>(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn a_peer_with_its_private_key_is_complete() {
+        let client = client();
+
+        assert!(client.is_complete());
+        assert!(client.interface_str().unwrap().is_some());
+        assert!(client.peer_str(&router()).is_ok());
+    }
+
+    #[test]
+    fn a_public_key_only_peer_is_server_side_only() {
+        let mut client = client();
+        client.set_private_key(None);
+
+        assert!(!client.is_complete());
+        assert!(client.interface_str().unwrap().is_none());
+        // the router still renders it
+        assert!(router().peer_str(&client).is_ok());
+    }
+
+    #[test]
+    fn a_peer_with_a_private_key_file_is_complete() {
+        let mut client = client();
+        client.set_private_key(None);
+        client.set_private_key_file(Some(PathBuf::from("/etc/wireguard/client.key")));
+
+        assert!(client.is_complete());
+    }
+
+    #[test]
+    fn wg_set_args_add_the_peer_with_its_router_allowed_ips() {
+        let client = client().with_routed_subnets(vec!["192.168.10.0/24".parse().unwrap()]);
+
+        assert_eq!(
+            wg_set_args("wg0", &client).unwrap(),
+            [
+                "set",
+                "wg0",
+                "peer",
+                PUBLIC_KEY,
+                "persistent-keepalive",
+                "25",
+                "allowed-ips",
+                "10.0.1.2/32,fd00::2/128,192.168.10.0/24"
+            ]
+        );
+    }
+
+    #[test]
+    fn wg_set_args_keep_an_explicitly_disabled_keepalive() {
+        let disabled = client().with_keepalive(Some(0));
+        let unset = client().with_keepalive(None);
+
+        assert_eq!(
+            wg_set_args("wg0", &disabled).unwrap()[4..6],
+            ["persistent-keepalive", "0"]
+        );
+        assert_eq!(wg_set_args("wg0", &unset).unwrap()[4], "allowed-ips");
+    }
+
+    #[test]
+    fn wg_remove_args_remove_the_peer() {
+        assert_eq!(
+            wg_remove_args("wg0", &client()).unwrap(),
+            ["set", "wg0", "peer", PUBLIC_KEY, "remove"]
+        );
+    }
+
+    #[test]
+    fn a_client_without_allowed_ips_still_renders() {
+        let mut client = client();
+        client.allowed_ips.clear();
+
+        let peer = client.peer_str(&router()).unwrap();
+        let sections = parse_wg_quick(&peer);
 
-        lines.join("\n")
+        assert_eq!(
+            sections[0].1,
+            ["PublicKey", "Endpoint", "PersistentKeepalive"]
+        );
     }
 }
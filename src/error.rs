@@ -0,0 +1,125 @@
+use ipnet::IpNet;
+use std::io;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Exit code of errors not covered by the others
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code of invalid command line arguments
+pub const EXIT_USAGE: i32 = 2;
+/// Exit code of a missing configuration or client
+pub const EXIT_NOT_FOUND: i32 = 3;
+/// Exit code of an invalid configuration or change
+pub const EXIT_INVALID: i32 = 4;
+/// Exit code of a failed file operation or key generation
+pub const EXIT_IO: i32 = 5;
+
+/// Errors of loading, changing and saving a configuration
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Configuration {} not found. Run `init` to create it.", .0.display())]
+    NotFound(PathBuf),
+    #[error("Configuration {} cannot be read, permission denied. Run as its owner or allow reading it with `chmod u+r {}`.", .0.display(), .0.display())]
+    PermissionDenied(PathBuf),
+    #[error("{}: the configuration name is taken from the file name", .0.display())]
+    InvalidName(PathBuf),
+    #[error("Invalid configuration: {0}")]
+    Parse(#[from] serde_yaml::Error),
+    #[error("Invalid TOML configuration: {}", .0.message())]
+    ParseToml(#[from] toml::de::Error),
+    #[error("Invalid JSON configuration: {0}")]
+    ParseJson(#[from] serde_json::Error),
+    #[error("Invalid configuration, neither TOML ({toml}) nor YAML ({yaml})")]
+    UnknownFormat { toml: String, yaml: String },
+    #[error("No path defined for this configuration")]
+    NoPath,
+    #[error("Could not find client {0}")]
+    ClientNotFound(String),
+    #[error("Client {0} not added")]
+    NotAdded(String),
+    #[error("Client {0} already exists")]
+    DuplicateName(String),
+    #[error("{address} is already used by {owner}")]
+    DuplicateAddress { address: IpAddr, owner: String },
+    #[error("{address} is outside of the router subnet {subnet}")]
+    OutsideSubnet { address: IpAddr, subnet: IpNet },
+    #[error("{address} is the network or broadcast address of {subnet}")]
+    NotHostAddress { address: IpAddr, subnet: IpNet },
+    #[error("The router has no subnet for {0}")]
+    NoSubnet(IpAddr),
+    #[error("No free address left in {0}")]
+    AddressExhausted(IpNet),
+    #[error("{0}'s private key is redacted, restore it from the original configuration")]
+    RedactedKey(String),
+    #[error("{0} mismatched or invalid keypairs")]
+    MismatchedKeys(usize),
+    #[error("{0}")]
+    Usage(&'static str),
+    #[error("{} already exists.", .0.display())]
+    AlreadyExists(PathBuf),
+    #[error("The {0} port must be in 1..=65535")]
+    InvalidPort(&'static str),
+    #[error("{0}")]
+    InvalidMtu(String),
+    #[error("{0} has no host address.")]
+    NoHostAddress(IpNet),
+    #[error("{0} is not reserved.")]
+    NotReserved(IpNet),
+    #[error("{0} is already reserved.")]
+    AlreadyReserved(IpNet),
+    #[error("The router has no endpoint {index}, valid indices are 0 to {}", .count - 1)]
+    NoEndpoint { index: usize, count: usize },
+    #[error("{count} peers match the client {criterion}, refusing to remove any")]
+    AmbiguousClient { count: usize, criterion: String },
+    #[error("Router {0} reads its keys from files, they cannot be rotated")]
+    KeysFromFiles(String),
+    #[error("Client {0} reads its keys from files, they cannot be rotated")]
+    ClientKeysFromFiles(String),
+    #[error("Client {0} was added with an external public key, its keys cannot be rotated")]
+    ExternalKeys(String),
+    #[error("Changes saved but not applied to the interface: {0}")]
+    NotApplied(String),
+    #[error("Cannot generate keys: {0}")]
+    KeyGen(io::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl ConfigError {
+    /// The code the process exits with when failing with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::NotFound(_) | Self::ClientNotFound(_) => EXIT_NOT_FOUND,
+            Self::InvalidName(_)
+            | Self::Parse(_)
+            | Self::ParseToml(_)
+            | Self::ParseJson(_)
+            | Self::UnknownFormat { .. }
+            | Self::NotAdded(_)
+            | Self::DuplicateName(_)
+            | Self::DuplicateAddress { .. }
+            | Self::OutsideSubnet { .. }
+            | Self::NotHostAddress { .. }
+            | Self::NoSubnet(_)
+            | Self::AddressExhausted(_)
+            | Self::RedactedKey(_)
+            | Self::MismatchedKeys(_)
+            | Self::InvalidPort(_)
+            | Self::InvalidMtu(_)
+            | Self::NoHostAddress(_)
+            | Self::NotReserved(_)
+            | Self::AlreadyReserved(_)
+            | Self::NoEndpoint { .. }
+            | Self::AmbiguousClient { .. }
+            | Self::KeysFromFiles(_)
+            | Self::ClientKeysFromFiles(_)
+            | Self::ExternalKeys(_) => EXIT_INVALID,
+            Self::Usage(_) => EXIT_USAGE,
+            Self::PermissionDenied(_) | Self::AlreadyExists(_) | Self::KeyGen(_) | Self::Io(_) => {
+                EXIT_IO
+            }
+            Self::NoPath | Self::NotApplied(_) => EXIT_FAILURE,
+        }
+    }
+}
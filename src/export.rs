@@ -0,0 +1,87 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Encrypts `data` to an age recipient (or SSH public key) by calling `age` on the host system
+pub fn age_encrypt(data: &[u8], recipient: &str) -> Result<Vec<u8>, io::Error> {
+    let mut command = Command::new("age")
+        .args(["--encrypt", "--recipient", recipient])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(e.kind(), "age not found in PATH"),
+            _ => e,
+        })?;
+
+    command
+        .stdin
+        .as_mut()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "cannot write to age"))?
+        .write_all(data)?;
+
+    let output = command.wait_with_output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `program` can be run, the tests needing it being skipped otherwise
+    fn is_available(program: &str) -> bool {
+        Command::new(program)
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+
+    #[test]
+    fn encrypted_config_decrypts_with_the_matching_identity() {
+        if !is_available("age") || !is_available("age-keygen") {
+            eprintln!("age not found, skipping");
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let identity = dir.path().join("identity.txt");
+        let keygen = Command::new("age-keygen")
+            .arg("-o")
+            .arg(&identity)
+            .output()
+            .unwrap();
+        assert!(keygen.status.success());
+
+        // age-keygen reports the recipient of the identity on stderr
+        let stderr = String::from_utf8(keygen.stderr).unwrap();
+        let recipient = stderr
+            .lines()
+            .find_map(|line| line.strip_prefix("Public key: "))
+            .unwrap()
+            .trim();
+
+        let config = b"[Interface]\nPrivateKey = secret\nAddress = 10.0.1.2/32\n";
+        let encrypted = age_encrypt(config, recipient).unwrap();
+        assert_ne!(encrypted.as_slice(), &config[..]);
+
+        let encrypted_path = dir.path().join("client.conf.age");
+        std::fs::write(&encrypted_path, &encrypted).unwrap();
+
+        let decrypted = Command::new("age")
+            .arg("--decrypt")
+            .arg("--identity")
+            .arg(&identity)
+            .arg(&encrypted_path)
+            .output()
+            .unwrap();
+        assert!(decrypted.status.success());
+        assert_eq!(decrypted.stdout, config);
+    }
+}
@@ -0,0 +1,397 @@
+use crate::compat::WgVersion;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::thread;
+
+/// Environment variable overriding the `wg` binary found in PATH
+pub const WG_BINARY_ENV: &str = "WG_BINARY";
+/// Name of the `wg` binary looked up in PATH
+const WG_BINARY_NAME: &str = if cfg!(windows) { "wg.exe" } else { "wg" };
+
+static WG_BINARY: OnceLock<PathBuf> = OnceLock::new();
+static WG_VERSION: OnceLock<Option<WgVersion>> = OnceLock::new();
+
+/// Length of a base64-encoded WireGuard key
+const ENCODED_KEY_LEN: usize = 44;
+/// Length of a raw WireGuard key
+const KEY_LEN: usize = 32;
+
+#[derive(Debug)]
+pub enum KeyError {
+    /// The key is not 44 characters long
+    Length(usize),
+    /// The key is not valid base64
+    Encoding(base64::DecodeError),
+    /// The key does not decode to 32 bytes
+    Size(usize),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Length(len) => write!(
+                f,
+                "key is {} characters long, expected {}",
+                len, ENCODED_KEY_LEN
+            ),
+            Self::Encoding(e) => write!(f, "key is not valid base64: {}", e),
+            Self::Size(size) => write!(f, "key decodes to {} bytes, expected {}", size, KEY_LEN),
+        }
+    }
+}
+
+impl Error for KeyError {}
+
+/// Checks that `s` is a well-formed WireGuard key: 44 base64 characters decoding to 32 bytes
+pub fn validate_wg_key(s: &str) -> Result<(), KeyError> {
+    if s.len() != ENCODED_KEY_LEN {
+        return Err(KeyError::Length(s.len()));
+    }
+
+    let decoded = STANDARD.decode(s).map_err(KeyError::Encoding)?;
+
+    if decoded.len() != KEY_LEN {
+        return Err(KeyError::Size(decoded.len()));
+    }
+
+    Ok(())
+}
+
+/// How keys are generated and derived
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyBackend {
+    /// Calling `wg` on the host system
+    Wg,
+    /// In-process, producing keys in the same format as `wg`
+    #[cfg(feature = "native-keys")]
+    Native,
+}
+
+impl KeyBackend {
+    /// Generates a private key and its public key
+    pub fn gen_keys(self) -> Result<(String, String), io::Error> {
+        match self {
+            KeyBackend::Wg => {
+                let privkey = run_wg(&["genkey"], None)?;
+                let pubkey = self.pubkey(&privkey)?;
+
+                Ok((privkey, pubkey))
+            }
+            #[cfg(feature = "native-keys")]
+            KeyBackend::Native => Ok(native::gen_keys()),
+        }
+    }
+
+    /// Generates a preshared key
+    pub fn gen_psk(self) -> Result<String, io::Error> {
+        match self {
+            KeyBackend::Wg => run_wg(&["genpsk"], None),
+            #[cfg(feature = "native-keys")]
+            KeyBackend::Native => Ok(native::gen_psk()),
+        }
+    }
+
+    /// Derives the public key matching `privkey`
+    pub fn pubkey(self, privkey: &str) -> Result<String, io::Error> {
+        match self {
+            KeyBackend::Wg => run_wg(&["pubkey"], Some(privkey)),
+            #[cfg(feature = "native-keys")]
+            KeyBackend::Native => native::pubkey(privkey),
+        }
+    }
+}
+
+/// Runs `f` with `wg`, falling back to the native backend when `wg` is missing and the
+/// `native-keys` feature is enabled
+fn with_fallback<T, F>(f: F) -> Result<T, io::Error>
+where
+    F: Fn(KeyBackend) -> Result<T, io::Error>,
+{
+    match f(KeyBackend::Wg) {
+        #[cfg(feature = "native-keys")]
+        Err(e) if e.kind() == io::ErrorKind::NotFound => f(KeyBackend::Native),
+        result => result,
+    }
+}
+
+/// Generates a keypair with the default backend
+pub fn gen_keys() -> Result<(String, String), io::Error> {
+    with_fallback(KeyBackend::gen_keys)
+}
+
+/// Generates `count` keypairs with the default backend, spread over a thread per CPU as
+/// each one may spawn `wg` twice. The keypairs come back in a stable order.
+pub fn gen_many_keys(count: usize) -> Result<Vec<(String, String)>, io::Error> {
+    gen_many_with(count, |_| gen_keys())
+}
+
+/// Calls `generate` with each index of `0..count` over a thread per CPU, returning the
+/// results in index order
+fn gen_many_with<T, F>(count: usize, generate: F) -> Result<Vec<T>, io::Error>
+where
+    T: Send,
+    F: Fn(usize) -> Result<T, io::Error> + Sync,
+{
+    let workers = thread::available_parallelism()
+        .map_or(1, |workers| workers.get())
+        .clamp(1, count.max(1));
+    let per_worker = count.div_ceil(workers);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|worker| {
+                let first = worker * per_worker;
+                let share = per_worker.min(count.saturating_sub(first));
+                let generate = &generate;
+
+                scope.spawn(move || {
+                    (first..first + share)
+                        .map(generate)
+                        .collect::<Result<Vec<_>, _>>()
+                })
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(count);
+
+        for handle in handles {
+            results.extend(handle.join().expect("Key generation thread panicked")?);
+        }
+
+        Ok(results)
+    })
+}
+
+/// Generates a preshared key with the default backend
+pub fn gen_psk() -> Result<String, io::Error> {
+    with_fallback(KeyBackend::gen_psk)
+}
+
+/// Derives the public key matching `privkey` with the default backend
+pub fn pubkey_from_privkey(privkey: &str) -> Result<String, io::Error> {
+    with_fallback(|backend| backend.pubkey(privkey))
+}
+
+/// Uses `path` as the `wg` binary, unless one has already been resolved
+pub fn set_wg_binary(path: PathBuf) {
+    let _ = WG_BINARY.set(path);
+}
+
+/// The `wg` binary: the one set with `set_wg_binary`, the one in `WG_BINARY` or the first
+/// one in PATH, resolved once. A `wg` missing from PATH is left bare, to fail when run.
+pub fn wg_binary() -> &'static Path {
+    WG_BINARY.get_or_init(|| {
+        if let Some(path) = env::var_os(WG_BINARY_ENV).filter(|path| !path.is_empty()) {
+            return PathBuf::from(path);
+        }
+
+        env::var_os("PATH")
+            .iter()
+            .flat_map(env::split_paths)
+            .map(|dir| dir.join(WG_BINARY_NAME))
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| PathBuf::from(WG_BINARY_NAME))
+    })
+}
+
+/// The version of the `wg` binary, if it runs and reports one, checked once
+pub fn wg_version() -> Option<&'static WgVersion> {
+    WG_VERSION
+        .get_or_init(|| {
+            // e.g. wireguard-tools v1.0.20210914 - https://git.zx2c4.com/wireguard-tools/
+            let output = run_wg(&["--version"], None).ok()?;
+
+            output.split_whitespace().nth(1)?.parse().ok()
+        })
+        .as_ref()
+}
+
+/// Runs `wg` with `args`, writing `input` to its stdin, and returns its trimmed output. A
+/// missing `wg` is reported as `NotFound`, a failure of `wg` itself as `InvalidInput`.
+pub fn run_wg(args: &[&str], input: Option<&str>) -> Result<String, io::Error> {
+    let mut command = Command::new(wg_binary())
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("wireguard-tools (`{}`) not found", wg_binary().display()),
+            ),
+            _ => e,
+        })?;
+
+    if let Some(input) = input {
+        command
+            .stdin
+            .as_mut()
+            .expect("Failed to get stdin for wg")
+            .write_all(input.as_bytes())?;
+    }
+
+    let output = command.wait_with_output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(feature = "native-keys")]
+mod native {
+    use super::{KEY_LEN, STANDARD};
+    use base64::Engine;
+    use rand_core::{OsRng, RngCore};
+    use std::convert::TryInto;
+    use std::io;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    fn random_bytes() -> [u8; KEY_LEN] {
+        let mut bytes = [0u8; KEY_LEN];
+        OsRng.fill_bytes(&mut bytes);
+        bytes
+    }
+
+    /// Generates a keypair, clamping the private key as `wg genkey` does
+    pub fn gen_keys() -> (String, String) {
+        let mut bytes = random_bytes();
+
+        bytes[0] &= 248;
+        bytes[31] &= 127;
+        bytes[31] |= 64;
+
+        let secret = StaticSecret::from(bytes);
+        let public = PublicKey::from(&secret);
+
+        (
+            STANDARD.encode(secret.to_bytes()),
+            STANDARD.encode(public.as_bytes()),
+        )
+    }
+
+    pub fn gen_psk() -> String {
+        STANDARD.encode(random_bytes())
+    }
+
+    pub fn pubkey(privkey: &str) -> Result<String, io::Error> {
+        let bytes: [u8; KEY_LEN] = STANDARD
+            .decode(privkey.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Key is not the correct length or format",
+                )
+            })?;
+
+        let public = PublicKey::from(&StaticSecret::from(bytes));
+
+        Ok(STANDARD.encode(public.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bulk_generation_keeps_the_order() {
+        for count in [0, 1, 7, 64] {
+            let results = gen_many_with(count, Ok).unwrap();
+
+            assert_eq!(results, (0..count).collect::<Vec<usize>>());
+        }
+    }
+
+    #[test]
+    fn bulk_generation_reports_failures() {
+        let result = gen_many_with(16, |i| match i {
+            11 => Err(io::Error::other("failed")),
+            i => Ok(i),
+        });
+
+        assert!(result.is_err());
+    }
+
+    #[cfg(feature = "native-keys")]
+    #[test]
+    fn bulk_generation_gives_unique_keypairs() {
+        use std::collections::HashSet;
+
+        let keypairs: Vec<(String, String)> =
+            gen_many_with(32, |_| KeyBackend::Native.gen_keys()).unwrap();
+        let private_keys: HashSet<&String> = keypairs.iter().map(|(private, _)| private).collect();
+        let public_keys: HashSet<&String> = keypairs.iter().map(|(_, public)| public).collect();
+
+        assert_eq!(private_keys.len(), 32);
+        assert_eq!(public_keys.len(), 32);
+
+        for (private_key, public_key) in &keypairs {
+            assert!(validate_wg_key(private_key).is_ok());
+            assert_eq!(&KeyBackend::Native.pubkey(private_key).unwrap(), public_key);
+        }
+    }
+
+    #[test]
+    fn keys_are_44_base64_characters_of_32_bytes() {
+        assert!(validate_wg_key("dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=").is_ok());
+
+        assert!(matches!(validate_wg_key("dwdt"), Err(KeyError::Length(4))));
+        assert!(matches!(
+            validate_wg_key("dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LC!="),
+            Err(KeyError::Encoding(_))
+        ));
+        // 33 bytes fit in 44 characters without padding
+        assert!(matches!(
+            validate_wg_key("dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCoA"),
+            Err(KeyError::Size(33))
+        ));
+    }
+
+    #[cfg(feature = "native-keys")]
+    #[test]
+    fn native_keys_are_clamped_like_wg_genkey() {
+        let (private_key, public_key) = KeyBackend::Native.gen_keys().unwrap();
+        let bytes = STANDARD.decode(&private_key).unwrap();
+
+        assert!(validate_wg_key(&private_key).is_ok());
+        assert!(validate_wg_key(&public_key).is_ok());
+        assert_eq!(bytes[0] & 7, 0);
+        assert_eq!(bytes[31] & 128, 0);
+        assert_eq!(bytes[31] & 64, 64);
+
+        assert!(validate_wg_key(&KeyBackend::Native.gen_psk().unwrap()).is_ok());
+    }
+
+    #[cfg(feature = "native-keys")]
+    #[test]
+    fn native_public_keys_follow_rfc_7748() {
+        // Alice's keys of RFC 7748, section 6.1
+        assert_eq!(
+            KeyBackend::Native
+                .pubkey("dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=\n")
+                .unwrap(),
+            "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo="
+        );
+
+        for private_key in ["", "dwdt", "not a key at all, not a key at all, not a k"] {
+            let error = KeyBackend::Native.pubkey(private_key).unwrap_err();
+
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{}", private_key);
+        }
+    }
+}
@@ -0,0 +1,18 @@
+//! Configuration manager for WireGuard: a router and its clients, kept in a YAML file, from
+//! which wg-quick configurations are rendered.
+
+#[macro_use]
+extern crate serde_derive;
+
+pub mod addrport;
+pub mod compat;
+pub mod configuration;
+pub mod endpoint;
+pub mod error;
+pub mod keys;
+pub mod validation;
+
+pub use crate::addrport::AddrPort;
+pub use crate::configuration::Configuration;
+pub use crate::endpoint::{Peer, Router, TableType};
+pub use crate::error::ConfigError;
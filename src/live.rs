@@ -0,0 +1,122 @@
+use ipnet::IpNet;
+use std::io;
+use wireguard_configure::keys;
+
+/// A peer of a running interface, as reported by `wg show <interface> dump`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LivePeer {
+    pub public_key: String,
+    /// Last known endpoint of the peer, if any
+    pub endpoint: Option<String>,
+    pub allowed_ips: Vec<IpNet>,
+    /// Seconds since the Unix epoch of the latest handshake, if there was one
+    pub latest_handshake: Option<u64>,
+    /// Bytes received from the peer
+    pub rx_bytes: u64,
+    /// Bytes sent to the peer
+    pub tx_bytes: u64,
+}
+
+/// Parses the output of `wg show <interface> dump`. The first line describes the interface
+/// itself and is skipped, as are malformed lines.
+pub fn parse_wg_dump(text: &str) -> Vec<LivePeer> {
+    text.lines()
+        .skip(1)
+        .filter_map(|line| {
+            // public-key, preshared-key, endpoint, allowed-ips, latest-handshake,
+            // transfer-rx, transfer-tx, persistent-keepalive
+            let fields: Vec<&str> = line.split('\t').collect();
+
+            if fields.len() != 8 {
+                return None;
+            }
+
+            let endpoint = Some(fields[2])
+                .filter(|endpoint| *endpoint != "(none)")
+                .map(str::to_string);
+            let allowed_ips = fields[3]
+                .split(',')
+                .filter_map(|ip| ip.trim().parse().ok())
+                .collect();
+            let latest_handshake = fields[4].parse().ok().filter(|seconds| *seconds != 0);
+
+            Some(LivePeer {
+                public_key: fields[0].to_string(),
+                endpoint,
+                allowed_ips,
+                latest_handshake,
+                rx_bytes: fields[5].parse().ok()?,
+                tx_bytes: fields[6].parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Retrieves the peers of the running `interface`
+pub fn live_peers(interface: &str) -> Result<Vec<LivePeer>, io::Error> {
+    let dump = keys::run_wg(&["show", interface, "dump"], None).map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidInput => io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("cannot read interface {}, is it up? wg: {}", interface, e),
+        ),
+        _ => e,
+    })?;
+
+    Ok(parse_wg_dump(&dump))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = "\
+cm91dGVyLXByaXZhdGUta2V5LTAwMDAwMDAwMDAwMDA=\tcm91dGVyLXB1YmxpYy1rZXktMDAwMDAwMDAwMDAwMDA=\t51820\toff
+Y2xpZW50LWEtcHVibGljLWtleS0wMDAwMDAwMDAwMDA=\t(none)\t198.51.100.7:40123\t10.0.1.2/32,fd00::2/128\t1700000000\t1024\t2048\t25
+Y2xpZW50LWItcHVibGljLWtleS0wMDAwMDAwMDAwMDA=\t(none)\t(none)\t(none)\t0\t0\t0\toff
+";
+
+    #[test]
+    fn dump_peers_are_parsed_and_the_interface_skipped() {
+        let peers = parse_wg_dump(DUMP);
+
+        assert_eq!(
+            peers,
+            vec![
+                LivePeer {
+                    public_key: "Y2xpZW50LWEtcHVibGljLWtleS0wMDAwMDAwMDAwMDA=".to_string(),
+                    endpoint: Some("198.51.100.7:40123".to_string()),
+                    allowed_ips: vec![
+                        "10.0.1.2/32".parse().unwrap(),
+                        "fd00::2/128".parse().unwrap()
+                    ],
+                    latest_handshake: Some(1700000000),
+                    rx_bytes: 1024,
+                    tx_bytes: 2048,
+                },
+                LivePeer {
+                    public_key: "Y2xpZW50LWItcHVibGljLWtleS0wMDAwMDAwMDAwMDA=".to_string(),
+                    endpoint: None,
+                    allowed_ips: vec![],
+                    latest_handshake: None,
+                    rx_bytes: 0,
+                    tx_bytes: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_dump_lines_are_skipped() {
+        let dump = "\
+interface
+truncated\t(none)\t(none)
+bad-transfer\t(none)\t(none)\t10.0.1.3/32\t0\tlots\t0\toff
+good\t(none)\t(none)\t10.0.1.4/32\t0\t1\t2\toff
+";
+        let peers = parse_wg_dump(dump);
+
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].public_key, "good");
+        assert!(parse_wg_dump("").is_empty());
+    }
+}
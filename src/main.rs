@@ -1,73 +1,193 @@
 #[macro_use]
 extern crate serde_derive;
 
-mod addrport;
 mod args;
-mod configuration;
-mod endpoint;
+mod export;
+mod live;
+#[cfg(feature = "interactive")]
+mod wizard;
 
-use crate::addrport::AddrPort;
-use crate::configuration::Configuration;
-use crate::endpoint::{Peer, Router};
-use args::{Arguments, SubCommand};
+use args::{AddClientArgs, Arguments, ListFormat, ListSort, SetRouterArgs, SubCommand};
 use atty::Stream;
+use chrono::{DateTime, SecondsFormat, Utc};
 use ipnet::IpNet;
-use prettytable::{Cell, Row, Table};
+use prettytable::{color, Attr, Cell, Row, Table};
 use std::error::Error;
-use std::io::Read;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
 use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
 use structopt::StructOpt;
+use wireguard_configure::compat::{self, WgVersion};
+use wireguard_configure::configuration::ConfigFormat;
+use wireguard_configure::validation::{self, Issue, Severity};
+use wireguard_configure::{configuration, endpoint, error, keys};
+use wireguard_configure::{AddrPort, ConfigError, Configuration, Peer, Router};
 
-fn example_configuration() -> Configuration {
+/// Where wg-quick looks for interface configurations
+const WG_QUICK_DIR: &str = "/etc/wireguard";
+/// Why --apply is refused for configurations without a name, which `wg set` needs
+const NO_INTERFACE_TO_APPLY: &str =
+    "Cannot apply changes: the configuration has no name to use as interface, open it by name";
+
+// Placeholder keys for the example configuration, so that its output is reproducible.
+// They are the base64 encoding of their own description and must never be used in production.
+const EXAMPLE_ROUTER_PRIVATE_KEY: &str = "ZXhhbXBsZS1yb3V0ZXItcHJpdmF0ZS1rZXktMDAwMDA=";
+const EXAMPLE_ROUTER_PUBLIC_KEY: &str = "ZXhhbXBsZS1yb3V0ZXItcHVibGljLWtleS0wMDAwMDA=";
+const EXAMPLE_CLIENT_A_PRIVATE_KEY: &str = "ZXhhbXBsZS1jbGllbnQtYS1wcml2YXRlLWtleS0wMDA=";
+const EXAMPLE_CLIENT_A_PUBLIC_KEY: &str = "ZXhhbXBsZS1jbGllbnQtYS1wdWJsaWMta2V5LTAwMDA=";
+const EXAMPLE_CLIENT_B_PRIVATE_KEY: &str = "ZXhhbXBsZS1jbGllbnQtYi1wcml2YXRlLWtleS0wMDA=";
+const EXAMPLE_CLIENT_B_PUBLIC_KEY: &str = "ZXhhbXBsZS1jbGllbnQtYi1wdWJsaWMta2V5LTAwMDA=";
+
+fn example_configuration(ipv6: bool) -> Configuration {
     // Router
-    let router_ip = "10.0.1.1/24".parse().unwrap();
-    let router_subnet = "10.0.1.0/24".parse().unwrap();
+    let router_ip = if ipv6 { "fd00::1/64" } else { "10.0.1.1/24" };
+    let router_ip: IpNet = router_ip.parse().unwrap();
+    let router_subnet = router_ip.trunc();
 
     // Client A
-    let client_a_ip = "10.0.1.2".parse().unwrap();
-    let client_a_dns = "10.0.1.1".parse().unwrap();
-    let client_a_allowed_ips = "0.0.0.0/0".parse().unwrap();
+    let client_a_ip = if ipv6 { "fd00::2" } else { "10.0.1.2" };
+    let client_a_ip = client_a_ip.parse().unwrap();
+    let client_a_dns = router_ip.addr();
+    let client_a_allowed_ips = if ipv6 { "::/0" } else { "0.0.0.0/0" };
+    let client_a_allowed_ips = client_a_allowed_ips.parse().unwrap();
 
     // Client B
-    let client_b_ip = "10.0.1.3".parse().unwrap();
+    let client_b_ip = if ipv6 { "fd00::3" } else { "10.0.1.3" };
+    let client_b_ip = client_b_ip.parse().unwrap();
 
-    let router = Router::new("vpn-router", router_ip, AddrPort::new("vpn.com", 31337));
+    let router = Router::from_keys(
+        "vpn-router",
+        router_ip,
+        AddrPort::new("vpn.com", 31337),
+        EXAMPLE_ROUTER_PRIVATE_KEY.to_string(),
+        EXAMPLE_ROUTER_PUBLIC_KEY.to_string(),
+    );
     let mut configuration = Configuration::new(router);
 
     configuration.push_peer(
-        Peer::new("client-a", client_a_ip)
-            .with_allowed_ips(client_a_allowed_ips)
-            .with_keepalive(Some(25))
-            .with_dns(Some(client_a_dns)),
+        Peer::from_keys(
+            "client-a",
+            client_a_ip,
+            Some(EXAMPLE_CLIENT_A_PRIVATE_KEY.to_string()),
+            EXAMPLE_CLIENT_A_PUBLIC_KEY.to_string(),
+        )
+        .with_allowed_ips(client_a_allowed_ips)
+        .with_keepalive(Some(25))
+        .with_dns(vec![client_a_dns]),
     );
 
     configuration.push_peer(
-        Peer::new("client-b", client_b_ip)
-            .with_allowed_ips(router_subnet)
-            .with_keepalive(Some(25)),
+        Peer::from_keys(
+            "client-b",
+            client_b_ip,
+            Some(EXAMPLE_CLIENT_B_PRIVATE_KEY.to_string()),
+            EXAMPLE_CLIENT_B_PUBLIC_KEY.to_string(),
+        )
+        .with_allowed_ips(router_subnet)
+        .with_keepalive(Some(25)),
     );
 
     configuration
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Arguments::from_args();
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(exit_code(e.as_ref()));
+    }
+}
+
+/// The code the process exits with when failing with `e`, as documented in the README
+fn exit_code(e: &(dyn Error + 'static)) -> i32 {
+    if let Some(e) = e.downcast_ref::<ConfigError>() {
+        e.exit_code()
+    } else if e.is::<io::Error>() {
+        error::EXIT_IO
+    } else {
+        error::EXIT_FAILURE
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = match Arguments::from_iter_safe(std::env::args_os()) {
+        Ok(args) => args,
+        // help and version are printed to stdout and exit successfully
+        Err(e) if e.use_stderr() => {
+            eprintln!("{}", e.message);
+            std::process::exit(error::EXIT_USAGE);
+        }
+        Err(e) => e.exit(),
+    };
+
+    if let Some(wg_path) = &args.wg_path {
+        keys::set_wg_binary(wg_path.clone());
+    }
+
+    if let SubCommand::GenerateExample { ipv6 } = args.subcommand {
+        println!("# Example configuration: its keys are placeholders, replace them before use");
+        println!("{}", example_configuration(ipv6).to_yaml(true));
+        return Ok(());
+    }
+
+    // neither does creating one
+    if let SubCommand::Init {
+        name,
+        internal_subnet,
+        endpoint,
+        verify_endpoint,
+    } = &args.subcommand
+    {
+        let path = match &args.config {
+            Some(path) => path.clone(),
+            None => Configuration::path_for_name(name),
+        };
+
+        return handle_init(
+            name,
+            *internal_subnet,
+            endpoint.clone(),
+            *verify_endpoint,
+            &path,
+            args.format.unwrap_or_else(|| ConfigFormat::for_path(&path)),
+        );
+    }
+
+    // importing does not need an existing configuration
+    if let SubCommand::Import {
+        path,
+        external_address,
+        allowed_ips,
+    } = &args.subcommand
+    {
+        return handle_import(
+            path,
+            external_address.as_deref(),
+            allowed_ips,
+            args.format.unwrap_or(ConfigFormat::Yaml),
+        );
+    }
 
-    if let SubCommand::GenerateExample = args.subcommand {
-        println!("{}", example_configuration());
+    // nor do shell completions
+    if let SubCommand::Completions { shell } = args.subcommand {
+        Arguments::clap().gen_completions_to(env!("CARGO_PKG_NAME"), shell, &mut io::stdout());
         return Ok(());
     }
 
-    // retrieve configuration either from config file (if specified) or stdin
-    let mut config = match args.config {
-        // from config file
-        Some(config) => Configuration::from_path(&config)?,
+    // retrieve configuration either from config file or name (if specified) or stdin
+    let mut config = match (args.config, args.name) {
+        // from config file, unless it is -
+        (Some(config), _) if config != Path::new("-") => read_config(&config, args.format)?,
+        // from the configuration directory
+        (None, Some(name)) => read_config(&Configuration::path_for_name(&name), args.format)?,
         // from stdin
-        None => {
+        _ => {
             // check if we are a tty
             if is_tty() {
-                println!("No configuration opened. Select a configuration file or pipe a configuration to stdin.");
-                return Ok(());
+                Err(ConfigError::Usage("No configuration opened. Select a configuration file or pipe a configuration to stdin."))?;
             }
 
             let stdin = std::io::stdin();
@@ -75,168 +195,2135 @@ fn main() -> Result<(), Box<dyn Error>> {
 
             stdin.lock().read_to_string(&mut stdin_data)?;
 
-            serde_yaml::from_str(&stdin_data)?
+            match args.format {
+                Some(format) => Configuration::from_str_as(&stdin_data, format)?,
+                None => Configuration::from_str_any(&stdin_data)?,
+            }
         }
     };
 
+    config = config
+        .with_reveal_secrets(args.reveal_secrets)
+        .with_dry_run(args.dry_run)
+        .with_verbose_comments(args.verbose_comments);
+
+    // a dry run changes nothing, not even the live interface
+    if args.dry_run {
+        let apply = match &args.subcommand {
+            SubCommand::AddClient(add_client_args) => add_client_args.apply,
+            SubCommand::RemoveClient { apply, .. } => *apply,
+            _ => false,
+        };
+
+        if apply {
+            Err(ConfigError::Usage(
+                "--apply cannot be combined with --dry-run",
+            ))?;
+        }
+    }
+
     match args.subcommand {
-        SubCommand::AddClient {
-            client_name,
-            internal_address,
-            allowed_ips,
-            dns,
-            persistent_keepalive,
-            public_key,
-        } => {
-            handle_add_client(
-                &mut config,
-                &client_name,
-                internal_address,
-                allowed_ips,
-                dns,
-                persistent_keepalive,
-                public_key,
-            )
-            .expect("Failed to add client.");
+        SubCommand::AddClient(add_client_args) => {
+            handle_add_client(&mut config, add_client_args)?;
 
             Ok(())
         }
-        SubCommand::ClientConfig { client_name } => {
-            handle_client_config(&config, &client_name);
-            Ok(())
+        SubCommand::Check => handle_check(&config),
+        SubCommand::VerifyKeys => handle_verify_keys(&config),
+        SubCommand::Stats { interface } => handle_stats(&config, interface),
+        SubCommand::Diff { interface } => handle_diff(&config, interface),
+        SubCommand::Export { output } => handle_export(&config, &output),
+        SubCommand::ExportAll { dir } => handle_export_all(&config, &dir),
+        SubCommand::Mesh { dir } => handle_mesh(&config, &dir),
+        SubCommand::ClientConfig {
+            client_name,
+            endpoint_index,
+            resolve,
+            target_version,
+            output,
+        } => handle_client_config(
+            &config,
+            &client_name,
+            endpoint_index,
+            resolve,
+            target_version.as_ref(),
+            output.as_deref(),
+        ),
+        SubCommand::ExportEncrypted {
+            client_name,
+            recipient,
+            output,
+        } => {
+            let output =
+                output.unwrap_or_else(|| PathBuf::from(format!("{}.conf.age", client_name)));
+
+            handle_export_encrypted(&config, &client_name, &recipient, &output)
         }
         // TODO: ugly
-        SubCommand::GenerateExample => {
-            println!("{}", example_configuration());
-            Ok(())
+        SubCommand::Init { .. }
+        | SubCommand::Import { .. }
+        | SubCommand::Completions { .. }
+        | SubCommand::GenerateExample { .. } => {
+            unreachable!("handled before loading a configuration")
         }
-        SubCommand::List => {
-            handle_list(&config);
-            Ok(())
+        SubCommand::List {
+            output_format,
+            tag,
+            sort,
+            no_color,
+            wide,
+            full_keys,
+        } => {
+            let clients = listed_clients(&config, tag.as_deref(), sort.as_ref());
+
+            match output_format {
+                ListFormat::Table => handle_list(&config, &clients, no_color, wide, full_keys),
+                ListFormat::Json => handle_list_json(&config, &clients),
+            }
         }
-        SubCommand::RemoveClient { client_name } => {
-            handle_remove_client(&mut config, &client_name).expect("Failed to remove client.");
+        SubCommand::Inventory { .. } => handle_inventory(&config),
+        SubCommand::Pool => {
+            handle_pool(&config);
             Ok(())
         }
-        SubCommand::RouterConfig => {
-            handle_router_config(&config);
+        SubCommand::Reserve { cidr, remove } => Ok(handle_reserve(&mut config, cidr, remove)?),
+        SubCommand::RemoveClient {
+            client_name,
+            by_address,
+            by_pubkey,
+            apply,
+            record,
+        } => {
+            let selector = match (client_name, by_address, by_pubkey) {
+                (Some(name), _, _) => ClientSelector::Name(name),
+                (_, Some(address), _) => ClientSelector::Address(address),
+                (_, _, Some(key)) => ClientSelector::PublicKey(key),
+                _ => Err(ConfigError::Usage("No client selected"))?,
+            };
+
+            handle_remove_client(&mut config, &selector, apply, record.as_deref())?;
             Ok(())
         }
+        SubCommand::Prune { include_disabled } => handle_prune(&mut config, include_disabled),
+        SubCommand::RotateKeys { client_name } => handle_rotate_keys(&mut config, &client_name),
+        SubCommand::RotateRouterKeys { yes } => Ok(handle_rotate_router_keys(&mut config, yes)?),
+        SubCommand::MoveClient { client_name, to } => {
+            handle_move_client(&mut config, &client_name, &to)
+        }
+        SubCommand::Show {
+            client_name,
+            reveal,
+        } => handle_show(&config, &client_name, reveal),
+        SubCommand::SetRouter(set_router_args) => {
+            Ok(handle_set_router(&mut config, set_router_args)?)
+        }
+        SubCommand::SetEndpoint {
+            endpoint,
+            alternates,
+            clear_alternates,
+            verify_endpoint,
+        } => handle_set_endpoint(
+            &mut config,
+            endpoint,
+            alternates,
+            clear_alternates,
+            verify_endpoint,
+        ),
+        SubCommand::Up => handle_wg_quick(&config, &["up"]),
+        SubCommand::Down => handle_wg_quick(&config, &["down"]),
+        SubCommand::Reload => handle_wg_quick(&config, &["down", "up"]),
+        SubCommand::RouterConfig {
+            tag,
+            target_version,
+            output,
+        } => handle_router_config(
+            &config,
+            tag.as_deref(),
+            target_version.as_ref(),
+            output.as_deref(),
+        ),
     }
 }
 
 fn handle_add_client(
     config: &mut Configuration,
-    client_name: &str,
-    internal_address: IpAddr,
-    allowed_ips: Vec<IpNet>,
-    dns: Option<IpAddr>,
-    persistent_keepalive: Option<usize>,
-    public_key: Option<String>,
+    args: AddClientArgs,
 ) -> Result<(), Box<dyn Error>> {
-    // check if the client we are trying to add already exists
-    if config
-        .clients
+    // missing settings are asked for when running interactively, scripts must give them all
+    #[cfg(feature = "interactive")]
+    let args = {
+        let mut args = args;
+
+        if args.internal_address.is_none() && args.count.is_none() && is_tty() {
+            wizard::complete_add_client(config, &mut args)?;
+        }
+
+        args
+    };
+
+    if let Some(count) = args.count {
+        return handle_add_clients(config, args, count);
+    }
+
+    let apply = args.apply;
+    let force = args.force;
+    let interface = interface_name(config);
+
+    if apply && interface.is_none() {
+        Err(ConfigError::Usage(NO_INTERFACE_TO_APPLY))?;
+    }
+
+    let peer = new_client(config, args, None)?;
+
+    let wg_args = endpoint::wg_set_args(&interface.unwrap_or_default(), &peer);
+    let summary = added_client_summary(&peer)?;
+
+    // updating configuration
+    insert_client(config, peer, force)?;
+
+    config.save()?;
+
+    if !config.writes_to_stdout() {
+        println!("{}", summary);
+    }
+
+    if apply {
+        let applied = wg_args
+            .map_err(|e| e.to_string())
+            .and_then(|wg_args| apply_to_interface(&wg_args));
+
+        applied.map_err(ConfigError::NotApplied)?;
+    }
+
+    Ok(())
+}
+
+/// What to tell about a client once added: its addresses, its public key and how to get its
+/// configuration
+fn added_client_summary(peer: &Peer) -> Result<String, io::Error> {
+    let addresses: Vec<String> = peer
+        .internal_addresses()
         .iter()
-        .any(|client| client.name == client_name)
-    {
-        eprintln!("Client {} already exists", client_name);
-        return Ok(());
+        .map(|address| address.to_string())
+        .collect();
+
+    Ok(format!(
+        "Client {} added\n  Address:    {}\n  Public key: {}\nPrint its configuration with `client-config {}`, or write every client's with `export-all <dir>`",
+        peer.name,
+        addresses.join(", "),
+        peer.load_public_key()?,
+        peer.name
+    ))
+}
+
+/// Adds `count` clients named after the prefix, with the next free addresses. Either every
+/// client is added or none is.
+fn handle_add_clients(
+    config: &mut Configuration,
+    args: AddClientArgs,
+    count: usize,
+) -> Result<(), Box<dyn Error>> {
+    let prefix = args.name_prefix.clone().unwrap_or_default();
+    let interface = interface_name(config);
+
+    if args.apply && interface.is_none() {
+        Err(ConfigError::Usage(NO_INTERFACE_TO_APPLY))?;
+    }
+
+    // the clients are added to a copy, so that a failure leaves the configuration untouched
+    let mut batch = config.clone();
+    let mut added = Vec::with_capacity(count);
+    let keypairs = keys::gen_many_keys(count).map_err(ConfigError::KeyGen)?;
+
+    for (number, keypair) in (1..=count).zip(keypairs) {
+        let internal_address = match batch.next_available_address() {
+            Some(internal_address) => internal_address,
+            None => Err(ConfigError::AddressExhausted(
+                config.router.internal_address.trunc(),
+            ))?,
+        };
+        let client_args = AddClientArgs {
+            client_name: Some(format!("{}{}", prefix, number)),
+            internal_address: Some(internal_address),
+            count: None,
+            name_prefix: None,
+            ..args.clone()
+        };
+
+        let peer = new_client(&batch, client_args, Some(keypair))?;
+
+        insert_client(&mut batch, peer.clone(), args.force)?;
+        added.push(peer);
+    }
+
+    *config = batch;
+    config.save()?;
+
+    if !config.writes_to_stdout() {
+        println!("{} clients added", added.len());
+    }
+
+    if args.apply {
+        let mut failed = 0;
+
+        for peer in &added {
+            let applied = endpoint::wg_set_args(&interface.clone().unwrap_or_default(), peer)
+                .map_err(|e| e.to_string())
+                .and_then(|wg_args| apply_to_interface(&wg_args));
+
+            if let Err(e) = applied {
+                eprintln!("Cannot apply changes for {}: {}", peer.name, e);
+                failed += 1;
+            }
+        }
+
+        if failed > 0 {
+            Err(ConfigError::NotApplied(format!(
+                "{} of {} clients failed",
+                failed,
+                added.len()
+            )))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds a client, with the checks of `Configuration::add_peer` unless `force`d, in which case
+/// it may lie outside of the router subnets
+fn insert_client(config: &mut Configuration, peer: Peer, force: bool) -> Result<(), ConfigError> {
+    if !force {
+        return config.add_peer(peer);
+    }
+
+    config.check_peer_conflicts(&peer)?;
+    config.push_peer(peer);
+
+    Ok(())
+}
+
+/// Builds the client described by the arguments, checked against the configuration, with the
+/// given keypair or a new one. Problems are reported and give no client.
+fn new_client(
+    config: &Configuration,
+    args: AddClientArgs,
+    keypair: Option<(String, String)>,
+) -> Result<Peer, Box<dyn Error>> {
+    let AddClientArgs {
+        client_name,
+        count: _,
+        name_prefix: _,
+        internal_address,
+        internal_address_v6,
+        description,
+        tags,
+        expires,
+        mut allowed_ips,
+        merge,
+        routed_subnets,
+        full_tunnel,
+        exclude,
+        split_tunnel,
+        mut dns,
+        mut dns_search,
+        mut persistent_keepalive,
+        behind_nat,
+        mtu,
+        jumbo,
+        table,
+        mut listen_port,
+        endpoint,
+        mesh_endpoint,
+        preup,
+        postup,
+        predown,
+        postdown,
+        public_key,
+        private_key_file,
+        private_key,
+        like,
+        check_subnet_alignment,
+        force,
+        apply: _,
+    } = args;
+    let client_name = match &client_name {
+        Some(client_name) => client_name.as_str(),
+        None => Err(ConfigError::Usage("A client name is required"))?,
+    };
+
+    let internal_address = match internal_address {
+        Some(internal_address) => internal_address,
+        None => Err(ConfigError::Usage(
+            "The internal address (-i) is required when not running interactively.",
+        ))?,
+    };
+    // clients of a dual-stack router get an IPv6 address as well
+    let internal_address_v6 = internal_address_v6.or_else(|| config.next_available_address_v6());
+
+    if full_tunnel {
+        allowed_ips = endpoint::subtract_cidrs(&["0.0.0.0/0".parse()?, "::/0".parse()?], &exclude);
+    }
+
+    if split_tunnel {
+        allowed_ips = config.router.subnets();
+    }
+
+    // settings not given explicitly are inherited from the referenced client, if any
+    if let Some(like) = like {
+        let template = match config.client_by_name(&like) {
+            Some(template) => template,
+            None => Err(ConfigError::ClientNotFound(like))?,
+        };
+
+        if allowed_ips.is_empty() {
+            allowed_ips = template.allowed_ips.clone();
+        }
+
+        if dns.is_empty() {
+            dns = template.dns.clone();
+        }
+
+        if dns_search.is_empty() {
+            dns_search = template.dns_search.clone();
+        }
+
+        if persistent_keepalive.is_none() {
+            persistent_keepalive = template.persistent_keepalive;
+        }
+    }
+
+    if persistent_keepalive.is_none() {
+        persistent_keepalive = config.default_keepalive;
+    }
+
+    if allowed_ips.is_empty() {
+        Err(ConfigError::Usage(
+            "Allowed IPs (-a) are required when not running interactively.",
+        ))?;
+    }
+
+    let allowed_ips = endpoint::dedup_allowed_ips(&allowed_ips, merge);
+
+    // mesh nodes reach the client at its mesh endpoint, which it must listen on
+    if listen_port.is_none() {
+        listen_port = mesh_endpoint.as_ref().map(|endpoint| endpoint.port);
+    }
+
+    if dns.is_empty() && !dns_search.is_empty() {
+        eprintln!("warning: DNS search domains are only used along with DNS servers (-d)");
     }
 
     // creating peer
-    let mut peer = Peer::new(client_name, internal_address)
+    let peer = match keypair {
+        Some((private_key, public_key)) => {
+            Peer::from_keys(client_name, internal_address, Some(private_key), public_key)
+        }
+        // the keys given are set below, there is nothing to generate
+        None if public_key.is_some() || private_key.is_some() || private_key_file.is_some() => {
+            Peer::from_keys(client_name, internal_address, None, String::new())
+        }
+        None => Peer::new(client_name, internal_address).map_err(ConfigError::KeyGen)?,
+    };
+    let mut peer = peer
+        .with_internal_address_v6(internal_address_v6)
+        .with_description(description)
+        .with_tags(tags)
+        .with_expires_at(expires)
         .with_dns(dns)
+        .with_dns_search(dns_search)
         .with_keepalive(persistent_keepalive)
-        .with_vec_allowed_ips(allowed_ips);
+        .with_behind_nat(behind_nat)
+        .with_mtu(mtu)
+        .with_table(table.clone())
+        .with_listen_port(listen_port)
+        .with_endpoint_override(endpoint)
+        .with_mesh_endpoint(mesh_endpoint)
+        .with_preup(preup)
+        .with_postup(postup)
+        .with_predown(predown)
+        .with_postdown(postdown)
+        .with_vec_allowed_ips(allowed_ips)
+        .with_routed_subnets(routed_subnets);
 
     if let Some(public_key) = public_key {
+        if let Err(e) = keys::validate_wg_key(&public_key) {
+            eprintln!("Invalid public key {}: {}", public_key, e);
+            return Err(ConfigError::NotAdded(client_name.to_string()).into());
+        }
+
         peer.set_private_key(None);
         peer.set_public_key(public_key);
     }
 
-    // updating configuration
-    config.push_peer(peer);
+    if let Some(private_key) = private_key {
+        if let Err(e) = keys::validate_wg_key(&private_key) {
+            eprintln!("Invalid private key: {}", e);
+            return Err(ConfigError::NotAdded(client_name.to_string()).into());
+        }
 
-    config.save()?;
+        peer.set_public_key(keys::pubkey_from_privkey(&private_key)?);
+        peer.set_private_key(Some(private_key));
+    }
+
+    // the private key stays in its file, only the derived public key is stored
+    if let Some(path) = private_key_file {
+        let private_key = match endpoint::read_key_file(&path) {
+            Ok(private_key) => private_key,
+            Err(e) => {
+                eprintln!("Cannot read private key file {}: {}", path.display(), e);
+                return Err(ConfigError::NotAdded(client_name.to_string()).into());
+            }
+        };
+
+        if let Err(e) = keys::validate_wg_key(&private_key) {
+            eprintln!("Invalid private key in {}: {}", path.display(), e);
+            return Err(ConfigError::NotAdded(client_name.to_string()).into());
+        }
+
+        peer.set_private_key(None);
+        peer.set_private_key_file(Some(std::fs::canonicalize(&path)?));
+        peer.set_public_key(keys::pubkey_from_privkey(&private_key)?);
+    }
+
+    let mut issues = config.check_listen_port(&peer);
+
+    if let Some(mtu) = mtu {
+        issues.extend(validation::check_mtu(
+            client_name,
+            mtu,
+            jumbo,
+            peer.has_ipv6(),
+        ));
+    }
+
+    if let Some(keepalive) = persistent_keepalive {
+        issues.extend(validation::check_keepalive(client_name, keepalive));
+    }
+
+    if let Some(table) = &table {
+        issues.extend(validation::check_table(client_name, table));
+    }
+
+    // the client must be reachable through the router subnet, unless forced
+    if !force {
+        issues.extend(config.check_peer_membership(&peer));
+    }
+
+    // checking the client address against the router subnet conventions, if requested
+    if check_subnet_alignment {
+        issues.extend(config.check_peer_alignment(&peer));
+    }
+
+    for issue in &issues {
+        eprintln!("{}", issue);
+    }
+
+    if issues.iter().any(|issue| issue.is_error()) {
+        return Err(ConfigError::NotAdded(client_name.to_string()).into());
+    }
+
+    Ok(peer)
+}
+
+fn handle_check(config: &Configuration) -> Result<(), Box<dyn Error>> {
+    let mut sections: Vec<(&str, Vec<Issue>)> = vec![
+        ("configuration", config.validate()),
+        ("endpoint", config.check_endpoint()),
+    ];
+
+    // environment
+    let wg = keys::wg_binary().display();
+    let mut environment = match Command::new(keys::wg_binary()).arg("--version").output() {
+        Ok(output) if output.status.success() => Vec::new(),
+        Ok(_) => vec![Issue::error(format!("`{} --version` failed", wg))],
+        Err(e) if e.kind() == io::ErrorKind::NotFound && cfg!(feature = "native-keys") => {
+            vec![Issue::warning(format!(
+                "wireguard-tools (`{}`) not found, keys are handled natively",
+                wg
+            ))]
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            vec![Issue::error(format!(
+                "wireguard-tools (`{}`) not found",
+                wg
+            ))]
+        }
+        Err(e) => vec![Issue::error(format!("cannot run `{}`: {}", wg, e))],
+    };
+
+    // the directives of the router configuration must be understood by the local wg
+    if let Some(version) = keys::wg_version() {
+        environment.extend(
+            compat::compatibility_warnings(&config.router_conf_string()?, version)
+                .into_iter()
+                .map(Issue::warning),
+        );
+    }
+
+    let keys_available = !environment.iter().any(|issue| issue.is_error());
+
+    sections.push(("environment", environment));
+
+    // key consistency can only be verified through wg, or natively
+    let keys = if keys_available {
+        config.check_keys()?
+    } else {
+        vec![Issue::warning(
+            "skipped, `wg` is required to derive public keys",
+        )]
+    };
+
+    sections.push(("keys", keys));
+
+    let mut worst: Option<Severity> = None;
+
+    for (section, issues) in &sections {
+        if issues.is_empty() {
+            println!("[PASS] {}", section);
+        }
+
+        for issue in issues {
+            let label = match issue.severity {
+                Severity::Warning => "WARN",
+                Severity::Error => "FAIL",
+            };
+
+            println!("[{}] {}: {}", label, section, issue.message);
+            worst = worst.max(Some(issue.severity));
+        }
+    }
 
-    if !config.is_from_tty() {
-        println!("Client added");
+    match worst {
+        None => Ok(()),
+        Some(Severity::Warning) => std::process::exit(error::EXIT_FAILURE),
+        Some(Severity::Error) => std::process::exit(error::EXIT_INVALID),
+    }
+}
+
+/// Reports the peers whose public key is not derived from their private key
+fn handle_verify_keys(config: &Configuration) -> Result<(), Box<dyn Error>> {
+    let issues = config.check_keys()?;
+
+    for issue in &issues {
+        eprintln!("{}", issue);
     }
 
+    if !issues.is_empty() {
+        Err(ConfigError::MismatchedKeys(issues.len()))?;
+    }
+
+    println!("All keys match");
+
     Ok(())
 }
 
-fn handle_client_config(config: &Configuration, client_name: &str) {
-    match config.client_config(client_name) {
-        Some(config) => println!("{}", config),
-        None => println!("Could not find client {}", client_name),
+/// Prints the peers which applying the configuration would add (+), remove (-) or change (~)
+fn handle_diff(config: &Configuration, interface: Option<String>) -> Result<(), Box<dyn Error>> {
+    let interface = match interface.or_else(|| interface_name(config)) {
+        Some(interface) => interface,
+        None => Err(ConfigError::Usage(
+            "The configuration has no name to use as interface, use --interface.",
+        ))?,
+    };
+
+    let live_peers = live::live_peers(&interface)?;
+    let sorted = |ips: &[IpNet]| {
+        let mut ips = ips.to_vec();
+        ips.sort();
+        ips
+    };
+    let join = |ips: &[IpNet]| match ips {
+        [] => "(none)".to_string(),
+        _ => ips
+            .iter()
+            .map(|ip| ip.to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    let mut known_keys = Vec::new();
+    let mut differences = 0;
+
+    for client in config.clients.iter().filter(|client| client.enabled) {
+        let public_key = client.load_public_key()?;
+        let allowed_ips = sorted(&client.router_allowed_ips());
+
+        match live_peers.iter().find(|peer| peer.public_key == public_key) {
+            None => {
+                println!("+ {} ({}): {}", client.name, public_key, join(&allowed_ips));
+                differences += 1;
+            }
+            Some(peer) if sorted(&peer.allowed_ips) != allowed_ips => {
+                println!(
+                    "~ {}: allowed IPs {} on the interface, {} in the configuration",
+                    client.name,
+                    join(&sorted(&peer.allowed_ips)),
+                    join(&allowed_ips)
+                );
+                differences += 1;
+            }
+            Some(_) => {}
+        }
+
+        known_keys.push(public_key);
+    }
+
+    for peer in live_peers
+        .iter()
+        .filter(|peer| !known_keys.contains(&peer.public_key))
+    {
+        println!("- {}: {}", peer.public_key, join(&peer.allowed_ips));
+        differences += 1;
+    }
+
+    if differences == 0 {
+        println!("Interface {} matches the configuration", interface);
     }
+
+    Ok(())
 }
 
-fn handle_list(config: &Configuration) {
+/// Prints a table of the peers of the running interface, named after the configured client
+/// with the same public key, with their latest handshake and their traffic
+fn handle_stats(config: &Configuration, interface: Option<String>) -> Result<(), Box<dyn Error>> {
+    let interface = match interface.or_else(|| interface_name(config)) {
+        Some(interface) => interface,
+        None => Err(ConfigError::Usage(
+            "The configuration has no name to use as interface, use --interface.",
+        ))?,
+    };
+
+    // a missing interface is expected when it is down
+    let live_peers = match live::live_peers(&interface) {
+        Ok(live_peers) => live_peers,
+        Err(e) => {
+            eprintln!("No statistics for {}: {}", interface, e);
+            return Ok(());
+        }
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
     let mut table = Table::new();
 
     table.add_row(Row::new(vec![
         Cell::new("Name"),
-        Cell::new("Internal Address"),
-        Cell::new("Allowed IPs"),
+        Cell::new("Latest Handshake"),
+        Cell::new("Received"),
+        Cell::new("Sent"),
     ]));
 
-    table.add_row(Row::new(vec![
-        Cell::new(&config.router.name),
-        Cell::new(&format!("{}", config.router.internal_address)),
-        Cell::new(""),
-    ]));
+    for peer in &live_peers {
+        let name = config
+            .clients
+            .iter()
+            .find(|client| {
+                client
+                    .load_public_key()
+                    .is_ok_and(|key| key == peer.public_key)
+            })
+            .map(|client| client.name.as_str())
+            .unwrap_or("unknown");
+        let handshake = match peer.latest_handshake {
+            Some(handshake) => format!("{} ago", duration_text(now.saturating_sub(handshake))),
+            None => "never".to_string(),
+        };
 
-    for client in &config.clients {
         table.add_row(Row::new(vec![
-            Cell::new(&client.name),
-            Cell::new(&format!("{}", client.internal_address)),
-            Cell::new(
-                &client
-                    .allowed_ips
-                    .iter()
-                    .map(|ip| format!("{}", ip))
-                    .collect::<Vec<String>>()
-                    .join(","),
-            ),
+            Cell::new(name),
+            Cell::new(&handshake),
+            Cell::new(&bytes_text(peer.rx_bytes)),
+            Cell::new(&bytes_text(peer.tx_bytes)),
         ]));
     }
 
-    table.printstd();
+    table.print(&mut io::stdout())?;
+
+    Ok(())
 }
 
-fn handle_remove_client(
-    config: &mut Configuration,
-    client_name: &str,
-) -> Result<(), Box<dyn Error>> {
-    let old_clients_len = config.clients.len();
+/// A duration in its largest unit, e.g. `3 minutes`
+fn duration_text(seconds: u64) -> String {
+    let (count, unit) = match seconds {
+        0..=59 => (seconds, "second"),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86_399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86_400, "day"),
+    };
 
-    config.clients.retain(|x| x.name != client_name);
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
 
-    if config.clients.len() == old_clients_len {
-        println!("Could not find and remove client \"{}\"", client_name);
-        return Ok(());
+/// A byte count in binary units, e.g. `1.5 MiB`
+fn bytes_text(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
     }
 
-    config.save()?;
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
 
-    if !config.is_from_tty() {
-        println!("Client {} removed", client_name);
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
     }
 
-    Ok(())
+    format!("{:.1} {}", value, UNITS[unit])
 }
 
-fn handle_router_config(config: &Configuration) {
-    println!("{}\n", config.router.interface_str());
+fn handle_client_config(
+    config: &Configuration,
+    client_name: &str,
+    endpoint_index: Option<usize>,
+    resolve: bool,
+    target_version: Option<&WgVersion>,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let endpoints = config.router.endpoints().len();
 
-    for client in &config.clients {
-        println!("{}\n", config.router.peer_str(&client));
+    if let Some(index) = endpoint_index.filter(|&index| index >= endpoints) {
+        Err(ConfigError::NoEndpoint {
+            index,
+            count: endpoints,
+        })?;
     }
-}
 
-fn is_tty() -> bool {
-    atty::is(Stream::Stdin)
+    let client_config = if resolve {
+        config.client_config_resolved(client_name, endpoint_index)?
+    } else {
+        config.client_config_for_endpoint(client_name, endpoint_index)?
+    };
+
+    match client_config {
+        Some(client_config) => {
+            warn_compatibility(&client_config, target_version);
+            warn_no_allowed_ips(config, client_name);
+            write_output(&format!("{}\n", client_config), output)?;
+        }
+        None => Err(missing_client_config(config, client_name))?,
+    }
+
+    Ok(())
+}
+
+/// Warns if the client has no allowed IPs, as its configuration would route nothing
+fn warn_no_allowed_ips(config: &Configuration, client_name: &str) {
+    if let Some(issue) = config
+        .client_by_name(client_name)
+        .and_then(|client| config.check_peer_allowed_ips(client))
+    {
+        eprintln!("{}", issue);
+    }
+}
+
+fn handle_export_encrypted(
+    config: &Configuration,
+    client_name: &str,
+    recipient: &str,
+    output: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let client_config = match config.client_config(client_name)? {
+        Some(client_config) => client_config,
+        None => Err(missing_client_config(config, client_name))?,
+    };
+
+    warn_no_allowed_ips(config, client_name);
+
+    let encrypted = export::age_encrypt(format!("{}\n", client_config).as_bytes(), recipient)?;
+
+    std::fs::write(output, encrypted)?;
+
+    println!("Encrypted configuration written to {}", output.display());
+
+    Ok(())
+}
+
+/// Explains why no client configuration could be produced for `client_name`
+fn missing_client_config(config: &Configuration, client_name: &str) -> Box<dyn Error> {
+    match config.client_by_name(client_name) {
+        Some(_) => format!(
+            "Client {} has no private key, only its router-side configuration is available",
+            client_name
+        )
+        .into(),
+        None => ConfigError::ClientNotFound(client_name.to_string()).into(),
+    }
+}
+
+/// Reads the configuration file at `path`, in `format` if given, in the format of its extension
+/// otherwise
+fn read_config(path: &Path, format: Option<ConfigFormat>) -> Result<Configuration, ConfigError> {
+    match format {
+        Some(format) => Configuration::from_path_as(path, format),
+        None => Configuration::from_path(path),
+    }
+}
+
+fn handle_init(
+    name: &str,
+    internal_subnet: IpNet,
+    endpoint: AddrPort,
+    verify_endpoint: bool,
+    path: &Path,
+    format: ConfigFormat,
+) -> Result<(), Box<dyn Error>> {
+    if path.exists() {
+        Err(ConfigError::AlreadyExists(path.to_path_buf()))?;
+    }
+
+    if verify_endpoint {
+        warn_unresolved_endpoint(&endpoint);
+    }
+
+    // a bare subnet gives the router its first host address, past the IPv6 subnet-router
+    // anycast address
+    let internal_address = if internal_subnet.addr() == internal_subnet.network() {
+        let first_host = match internal_subnet {
+            IpNet::V6(subnet) if subnet.prefix_len() < 128 => subnet.hosts().nth(1).map(IpAddr::V6),
+            _ => internal_subnet.hosts().next(),
+        };
+
+        match first_host {
+            Some(address) => IpNet::new(address, internal_subnet.prefix_len())?,
+            None => Err(ConfigError::NoHostAddress(internal_subnet))?,
+        }
+    } else {
+        internal_subnet
+    };
+
+    let router = Router::new(name, internal_address, endpoint).map_err(ConfigError::KeyGen)?;
+    let public_key = router.load_public_key()?;
+    let config = Configuration::new(router);
+
+    configuration::write_private_file(path, &config.to_format(format, true))
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+
+    println!("Configuration {} created in {}", name, path.display());
+    println!("Router public key: {}", public_key);
+
+    Ok(())
+}
+
+fn handle_import(
+    path: &Path,
+    external_address: Option<&str>,
+    allowed_ips: &[IpNet],
+    format: ConfigFormat,
+) -> Result<(), Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut config = Configuration::from_wg_conf(&text, allowed_ips)?;
+
+    match external_address {
+        Some(address) => config.router.external_address.address = address.to_string(),
+        None => eprintln!(
+            "warning: no --external-address given, set router.external_address.address before generating client configurations"
+        ),
+    }
+
+    // the imported configuration is meant to be saved, with its private keys
+    println!("{}", config.to_format(format, true));
+
+    Ok(())
+}
+
+fn handle_export_all(config: &Configuration, dir: &Path) -> Result<(), Box<dyn Error>> {
+    std::fs::create_dir_all(dir)?;
+
+    for client in &config.clients {
+        // clients added with an external public key cannot have a configuration
+        let client_config = match config.client_config(&client.name)? {
+            Some(client_config) => client_config,
+            None => {
+                println!("Skipping {}, its private key is unknown", client.name);
+                continue;
+            }
+        };
+
+        warn_no_allowed_ips(config, &client.name);
+
+        let path = dir.join(format!("{}.conf", client.name));
+
+        configuration::write_private_file(&path, &format!("{}\n", client_config))?;
+        println!("Configuration written to {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn handle_mesh(config: &Configuration, dir: &Path) -> Result<(), Box<dyn Error>> {
+    std::fs::create_dir_all(dir)?;
+
+    for (name, node_config) in config.mesh_configs()? {
+        let path = dir.join(format!("{}.conf", name));
+
+        configuration::write_private_file(&path, &format!("{}\n", node_config))?;
+        println!("Configuration written to {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn handle_export(config: &Configuration, output: &Path) -> Result<(), Box<dyn Error>> {
+    configuration::write_private_file(
+        output,
+        &config.to_format(ConfigFormat::for_path(output), true),
+    )?;
+
+    println!("Configuration written to {}", output.display());
+
+    Ok(())
+}
+
+/// Whether `client` should be listed, given an optional tag filter
+fn matches_tag(client: &Peer, tag: Option<&str>) -> bool {
+    tag.map_or(true, |tag| client.has_tag(tag))
+}
+
+/// The clients to list, in the configuration order unless sorted. Addresses and networks
+/// are compared as such, not as text.
+fn listed_clients<'a>(
+    config: &'a Configuration,
+    tag: Option<&str>,
+    sort: Option<&ListSort>,
+) -> Vec<&'a Peer> {
+    let mut clients: Vec<&Peer> = config
+        .clients
+        .iter()
+        .filter(|client| matches_tag(client, tag))
+        .collect();
+
+    match sort {
+        Some(ListSort::Name) => clients.sort_by(|a, b| a.name.cmp(&b.name)),
+        Some(ListSort::Address) => clients.sort_by_key(|client| client.internal_address),
+        Some(ListSort::Allowed) => clients.sort_by(|a, b| a.allowed_ips.cmp(&b.allowed_ips)),
+        None => {}
+    }
+
+    clients
+}
+
+fn handle_list(
+    config: &Configuration,
+    clients: &[&Peer],
+    no_color: bool,
+    wide: bool,
+    full_keys: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut table = Table::new();
+
+    // colors are only used on terminals, leaving piped output plain
+    let color = !no_color && std::env::var_os("NO_COLOR").is_none() && atty::is(Stream::Stdout);
+    let styled = |mut row: Row, attr: Attr| {
+        if color {
+            row.iter_mut().for_each(|cell| cell.style(attr));
+        }
+
+        row
+    };
+
+    // the columns of --wide: public key, DNS and MTU
+    let wide_cells = |public_key: String, dns: &[IpAddr], mtu: Option<u16>| {
+        let public_key = if full_keys {
+            public_key
+        } else {
+            public_key.chars().take(8).collect()
+        };
+
+        vec![
+            Cell::new(&public_key),
+            Cell::new(
+                &dns.iter()
+                    .map(|address| address.to_string())
+                    .collect::<Vec<String>>()
+                    .join(","),
+            ),
+            Cell::new(&mtu.map(|mtu| mtu.to_string()).unwrap_or_default()),
+        ]
+    };
+
+    let mut header = vec![
+        Cell::new("Name"),
+        Cell::new("Internal Address"),
+        Cell::new("Allowed IPs"),
+        Cell::new("Endpoint"),
+        Cell::new("Keepalive"),
+        Cell::new("Description"),
+    ];
+
+    if wide {
+        header.extend(vec![
+            Cell::new("Public Key"),
+            Cell::new("DNS"),
+            Cell::new("MTU"),
+        ]);
+    }
+
+    table.add_row(Row::new(header));
+
+    let mut router_cells = vec![
+        Cell::new(&config.router.name),
+        Cell::new(
+            &config
+                .router
+                .internal_addresses()
+                .iter()
+                .map(|address| format!("{}", address))
+                .collect::<Vec<String>>()
+                .join(","),
+        ),
+        Cell::new(""),
+        Cell::new(&format!("{}", config.router.external_address)),
+        Cell::new(""),
+        Cell::new(""),
+    ];
+
+    if wide {
+        router_cells.extend(wide_cells(
+            config.router.load_public_key()?,
+            &[],
+            config.router.mtu,
+        ));
+    }
+
+    table.add_row(styled(Row::new(router_cells), Attr::Bold));
+
+    for client in clients {
+        // expired clients are flagged even without colors
+        let name = if client.is_expired() {
+            format!("{} (expired)", client.name)
+        } else {
+            client.name.clone()
+        };
+        let mut cells = vec![
+            Cell::new(&name),
+            Cell::new(
+                &client
+                    .internal_addresses()
+                    .iter()
+                    .map(|address| format!("{}", address))
+                    .collect::<Vec<String>>()
+                    .join(","),
+            ),
+            Cell::new(
+                &client
+                    .allowed_ips
+                    .iter()
+                    .map(|ip| format!("{}", ip))
+                    .collect::<Vec<String>>()
+                    .join(","),
+            ),
+            Cell::new(
+                &client
+                    .endpoint_override
+                    .as_ref()
+                    .map(|endpoint| endpoint.to_string())
+                    .unwrap_or_default(),
+            ),
+            Cell::new(
+                &client
+                    .persistent_keepalive
+                    .or(client.keepalive())
+                    .map(keepalive_text)
+                    .unwrap_or_default(),
+            ),
+            Cell::new(client.description.as_deref().unwrap_or("")),
+        ];
+
+        if wide {
+            cells.extend(wide_cells(
+                client.load_public_key()?,
+                &client.dns,
+                client.mtu,
+            ));
+        }
+
+        let row = Row::new(cells);
+
+        table.add_row(if client.is_expired() {
+            styled(row, Attr::ForegroundColor(color::RED))
+        } else if client.enabled {
+            row
+        } else {
+            styled(row, Attr::Dim)
+        });
+    }
+
+    // printing to stdout directly skips the terminal reset sequences printstd adds
+    if color {
+        table.printstd();
+    } else {
+        table.print(&mut io::stdout())?;
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ListEntry<'a> {
+    name: &'a str,
+    internal_address: String,
+    internal_address_v6: Option<String>,
+    allowed_ips: &'a [IpNet],
+    description: Option<&'a str>,
+    tags: &'a [String],
+    enabled: bool,
+    expires_at: Option<DateTime<Utc>>,
+    expired: bool,
+}
+
+#[derive(Serialize)]
+struct ListOutput<'a> {
+    router: ListEntry<'a>,
+    clients: Vec<ListEntry<'a>>,
+}
+
+fn handle_list_json(config: &Configuration, clients: &[&Peer]) -> Result<(), Box<dyn Error>> {
+    let output = ListOutput {
+        router: ListEntry {
+            name: &config.router.name,
+            internal_address: config.router.internal_address.to_string(),
+            internal_address_v6: config.router.internal_address_v6.map(|a| a.to_string()),
+            allowed_ips: &[],
+            description: None,
+            tags: &[],
+            enabled: true,
+            expires_at: None,
+            expired: false,
+        },
+        clients: clients
+            .iter()
+            .map(|client| ListEntry {
+                name: &client.name,
+                internal_address: client.internal_address.to_string(),
+                internal_address_v6: client.internal_address_v6.map(|a| a.to_string()),
+                allowed_ips: &client.allowed_ips,
+                description: client.description.as_deref(),
+                tags: &client.tags,
+                enabled: client.enabled,
+                expires_at: client.expires_at,
+                expired: client.is_expired(),
+            })
+            .collect(),
+    };
+
+    println!("{}", serde_json::to_string_pretty(&output)?);
+
+    Ok(())
+}
+
+/// Name of an Ansible group made of the clients tagged `tag`, which may only contain
+/// letters, digits and underscores
+fn tag_group(tag: &str) -> String {
+    let tag: String = tag
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    format!("tag_{}", tag)
+}
+
+fn handle_inventory(config: &Configuration) -> Result<(), Box<dyn Error>> {
+    let router = &config.router;
+    let mut hostvars = serde_json::Map::new();
+    let mut groups = serde_json::Map::new();
+    let mut tag_groups: Vec<(String, Vec<&str>)> = Vec::new();
+
+    hostvars.insert(
+        router.name.clone(),
+        serde_json::json!({
+            "ansible_host": router.external_address.address,
+            "wireguard_port": router.external_address.port,
+            "wireguard_addresses": router.internal_addresses(),
+            "wireguard_public_key": router.load_public_key()?,
+        }),
+    );
+
+    for client in &config.clients {
+        hostvars.insert(
+            client.name.clone(),
+            serde_json::json!({
+                "wireguard_addresses": client.internal_addresses(),
+                "wireguard_public_key": client.load_public_key()?,
+                "wireguard_allowed_ips": client.allowed_ips,
+                "wireguard_tags": client.tags,
+                "wireguard_enabled": client.enabled,
+            }),
+        );
+
+        for tag in &client.tags {
+            let group = tag_group(tag);
+
+            match tag_groups.iter_mut().find(|(name, _)| *name == group) {
+                Some((_, hosts)) => hosts.push(&client.name),
+                None => tag_groups.push((group, vec![&client.name])),
+            }
+        }
+    }
+
+    let clients: Vec<&str> = config.clients.iter().map(|c| c.name.as_str()).collect();
+    let mut children = vec![
+        "wireguard_router".to_string(),
+        "wireguard_clients".to_string(),
+    ];
+
+    groups.insert(
+        "wireguard_router".to_string(),
+        serde_json::json!({ "hosts": [router.name] }),
+    );
+    groups.insert(
+        "wireguard_clients".to_string(),
+        serde_json::json!({ "hosts": clients }),
+    );
+
+    for (group, hosts) in tag_groups {
+        groups.insert(group.clone(), serde_json::json!({ "hosts": hosts }));
+        children.push(group);
+    }
+
+    groups.insert(
+        "all".to_string(),
+        serde_json::json!({ "children": children }),
+    );
+    groups.insert(
+        "_meta".to_string(),
+        serde_json::json!({ "hostvars": hostvars }),
+    );
+
+    println!("{}", serde_json::to_string_pretty(&groups)?);
+
+    Ok(())
+}
+
+fn handle_pool(config: &Configuration) {
+    let usage = config.pool_usage();
+
+    println!("Subnet:         {}", usage.subnet);
+    println!("Hosts:          {}", usage.total);
+    println!(
+        "Used:           {} ({:.1}%)",
+        usage.used,
+        usage.percentage()
+    );
+    println!(
+        "Next available: {}",
+        usage
+            .next_available
+            .map(|address| address.to_string())
+            .unwrap_or_else(|| "none, the subnet is full".to_string())
+    );
+}
+
+fn handle_reserve(
+    config: &mut Configuration,
+    cidr: Option<IpNet>,
+    remove: bool,
+) -> Result<(), ConfigError> {
+    let cidr = match cidr {
+        Some(cidr) => cidr,
+        None => {
+            for reserved in &config.reserved {
+                println!("{}", reserved);
+            }
+
+            return Ok(());
+        }
+    };
+
+    if remove {
+        if !config.unreserve(&cidr) {
+            Err(ConfigError::NotReserved(cidr))?;
+        }
+    } else {
+        if !config.reserve(cidr) {
+            Err(ConfigError::AlreadyReserved(cidr))?;
+        }
+
+        for client in config.clients.iter().filter(|client| {
+            client
+                .internal_addresses()
+                .iter()
+                .any(|address| cidr.contains(address))
+        }) {
+            eprintln!(
+                "warning: client {} already uses an address of {}",
+                client.name, cidr
+            );
+        }
+    }
+
+    config.save()?;
+
+    if !config.writes_to_stdout() {
+        if remove {
+            println!("Reservation of {} released", cidr);
+        } else {
+            println!("{} reserved", cidr);
+        }
+    }
+
+    Ok(())
+}
+
+/// How the client to remove is picked, names being unreliable after an import
+enum ClientSelector {
+    Name(String),
+    Address(IpAddr),
+    PublicKey(String),
+}
+
+impl ClientSelector {
+    fn matches(&self, client: &Peer) -> bool {
+        match self {
+            Self::Name(name) => client.name == *name,
+            Self::Address(address) => client.internal_addresses().contains(address),
+            Self::PublicKey(key) => client
+                .load_public_key()
+                .is_ok_and(|public_key| public_key == *key),
+        }
+    }
+}
+
+impl ClientSelector {
+    /// The selected client, as described after the word "client"
+    fn criterion(&self) -> String {
+        match self {
+            Self::Name(name) => format!("\"{}\"", name),
+            Self::Address(address) => format!("with address {}", address),
+            Self::PublicKey(key) => format!("with public key {}", key),
+        }
+    }
+}
+
+impl fmt::Display for ClientSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "client {}", self.criterion())
+    }
+}
+
+fn handle_remove_client(
+    config: &mut Configuration,
+    selector: &ClientSelector,
+    apply: bool,
+    record: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let interface = interface_name(config);
+
+    if apply && interface.is_none() {
+        Err(ConfigError::Usage(NO_INTERFACE_TO_APPLY))?;
+    }
+
+    let matching: Vec<String> = config
+        .clients
+        .iter()
+        .filter(|client| selector.matches(client))
+        .map(|client| client.name.clone())
+        .collect();
+
+    let name = match matching.as_slice() {
+        [name] => name,
+        [] => Err(ConfigError::ClientNotFound(selector.criterion()))?,
+        _ => Err(ConfigError::AmbiguousClient {
+            count: matching.len(),
+            criterion: selector.criterion(),
+        })?,
+    };
+
+    // the revocation is recorded first, so that no client is removed without a trace
+    if let (Some(record), false) = (record, config.dry_run) {
+        let client = config
+            .client_by_name(name)
+            .ok_or_else(|| ConfigError::ClientNotFound(name.clone()))?;
+
+        record_revocation(record, client)?;
+    }
+
+    let client = config
+        .remove_peer(name)
+        .ok_or_else(|| ConfigError::ClientNotFound(name.clone()))?;
+    let wg_args = endpoint::wg_remove_args(&interface.unwrap_or_default(), &client);
+
+    config.save()?;
+
+    if !config.writes_to_stdout() {
+        println!(
+            "Client {} ({}) removed",
+            client.name, client.internal_address
+        );
+    }
+
+    if apply {
+        let applied = wg_args
+            .map_err(|e| e.to_string())
+            .and_then(|wg_args| apply_to_interface(&wg_args));
+
+        applied.map_err(ConfigError::NotApplied)?;
+    }
+
+    Ok(())
+}
+
+/// Appends the revocation of `client` to the audit file at `path` as a JSON line
+fn record_revocation(path: &Path, client: &Peer) -> Result<(), Box<dyn Error>> {
+    let line = serde_json::json!({
+        "revoked_at": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        "client": client.name,
+        "public_key": client.load_public_key()?,
+    });
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+
+    writeln!(file, "{}", line)?;
+
+    Ok(())
+}
+
+fn handle_prune(config: &mut Configuration, include_disabled: bool) -> Result<(), Box<dyn Error>> {
+    let dry_run = config.dry_run;
+    let pruned = config.prune(include_disabled);
+
+    // a piped configuration is printed to stdout, the removals are not listed along with it
+    let listed = if dry_run || !config.writes_to_stdout() {
+        pruned.as_slice()
+    } else {
+        &[]
+    };
+
+    for client in listed {
+        let reason = if client.is_expired() {
+            "expired"
+        } else {
+            "disabled"
+        };
+
+        println!(
+            "{} {} ({})",
+            if dry_run { "Would remove" } else { "Removing" },
+            client.name,
+            reason
+        );
+    }
+
+    if dry_run {
+        println!("{} clients would be pruned", pruned.len());
+        return Ok(());
+    }
+
+    // saving once, after every removal, and printing a piped configuration even if unchanged
+    if !pruned.is_empty() || config.writes_to_stdout() {
+        config.save()?;
+    }
+
+    if !config.writes_to_stdout() {
+        println!("{} clients pruned", pruned.len());
+    }
+
+    Ok(())
+}
+
+/// A persistent keepalive as displayed, 0 being explicitly disabled
+fn keepalive_text(keepalive: usize) -> String {
+    if keepalive == 0 {
+        "0 (disabled)".to_string()
+    } else {
+        keepalive.to_string()
+    }
+}
+
+fn handle_show(
+    config: &Configuration,
+    client_name: &str,
+    reveal: bool,
+) -> Result<(), Box<dyn Error>> {
+    let client = match config.client_by_name(client_name) {
+        Some(client) => client,
+        None => Err(ConfigError::ClientNotFound(client_name.to_string()))?,
+    };
+
+    let or_none = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let join = |items: Vec<String>| or_none(Some(items.join(", ")).filter(|s| !s.is_empty()));
+
+    let private_key = match (client.load_private_key()?, &client.private_key_file) {
+        (Some(key), _) if reveal => key,
+        (Some(_), Some(path)) => format!("(read from {}, use --reveal to show)", path.display()),
+        (Some(_), None) => "(hidden, use --reveal to show)".to_string(),
+        (None, _) => "(external keypair)".to_string(),
+    };
+
+    let fields = vec![
+        ("Name", client.name.clone()),
+        ("Description", or_none(client.description.clone())),
+        ("Enabled", client.enabled.to_string()),
+        (
+            "Expires",
+            match client.expires_at {
+                Some(expires_at) if client.is_expired() => format!("{} (expired)", expires_at),
+                expires_at => or_none(expires_at.map(|e| e.to_string())),
+            },
+        ),
+        (
+            "Internal address",
+            join(
+                client
+                    .internal_addresses()
+                    .iter()
+                    .map(|address| address.to_string())
+                    .collect(),
+            ),
+        ),
+        (
+            "Allowed IPs",
+            join(client.allowed_ips.iter().map(|ip| ip.to_string()).collect()),
+        ),
+        (
+            "Routed subnets",
+            join(
+                client
+                    .routed_subnets
+                    .iter()
+                    .map(|net| net.to_string())
+                    .collect(),
+            ),
+        ),
+        (
+            "DNS",
+            join(client.dns.iter().map(|ip| ip.to_string()).collect()),
+        ),
+        ("DNS search", join(client.dns_search.clone())),
+        (
+            "Keepalive",
+            or_none(
+                client
+                    .persistent_keepalive
+                    .or(client.keepalive())
+                    .map(keepalive_text),
+            ),
+        ),
+        ("Behind NAT", client.behind_nat.to_string()),
+        (
+            "Listen port",
+            or_none(client.listen_port.map(|p| p.to_string())),
+        ),
+        (
+            "Endpoint",
+            or_none(client.endpoint_override.as_ref().map(|e| e.to_string())),
+        ),
+        (
+            "Mesh endpoint",
+            or_none(client.mesh_endpoint.as_ref().map(|e| e.to_string())),
+        ),
+        ("MTU", or_none(client.mtu.map(|mtu| mtu.to_string()))),
+        (
+            "Table",
+            or_none(client.table.as_ref().map(|t| t.to_string())),
+        ),
+        ("PreUp", or_none(client.preup.clone())),
+        ("PostUp", or_none(client.postup.clone())),
+        ("PreDown", or_none(client.predown.clone())),
+        ("PostDown", or_none(client.postdown.clone())),
+        ("Tags", join(client.tags.clone())),
+        ("Public key", client.load_public_key()?),
+        ("Private key", private_key),
+    ];
+
+    for (key, value) in fields {
+        println!("{:<17} {}", format!("{}:", key), value);
+    }
+
+    Ok(())
+}
+
+fn handle_rotate_keys(config: &mut Configuration, client_name: &str) -> Result<(), Box<dyn Error>> {
+    let client = match config
+        .clients
+        .iter_mut()
+        .find(|client| client.name == client_name)
+    {
+        Some(client) => client,
+        None => Err(ConfigError::ClientNotFound(client_name.to_string()))?,
+    };
+
+    // the keypair of a client added with an external public key is not managed by us
+    if !client.is_complete() {
+        Err(ConfigError::ExternalKeys(client_name.to_string()))?;
+    }
+
+    if client.private_key_file.is_some() || client.public_key_file.is_some() {
+        Err(ConfigError::ClientKeysFromFiles(client_name.to_string()))?;
+    }
+
+    client.rotate_keys().map_err(ConfigError::KeyGen)?;
+
+    let public_key = client.public_key.clone();
+
+    config.save()?;
+
+    if !config.writes_to_stdout() {
+        println!("Keys rotated for client {}", client_name);
+        println!("New public key: {}", public_key);
+    }
+
+    Ok(())
+}
+
+fn handle_move_client(
+    config: &mut Configuration,
+    client_name: &str,
+    to: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut client = match config.client_by_name(client_name) {
+        Some(client) => client.clone(),
+        None => Err(ConfigError::ClientNotFound(client_name.to_string()))?,
+    };
+
+    let path = if to.is_file() {
+        to.to_path_buf()
+    } else {
+        Configuration::path_for_name(&to.to_string_lossy())
+    };
+    let mut destination = Configuration::from_path(&path)?
+        .with_reveal_secrets(config.reveal_secrets)
+        .with_dry_run(config.dry_run);
+
+    if destination.client_by_name(client_name).is_some() {
+        Err(ConfigError::DuplicateName(client_name.to_string()))?;
+    }
+
+    // addresses taken or outside of the destination subnets are replaced by free ones
+    if !destination.is_address_free(client.internal_address) {
+        let address = destination
+            .next_available_address()
+            .ok_or(ConfigError::AddressExhausted(
+                destination.router.internal_address.trunc(),
+            ))?;
+
+        eprintln!(
+            "warning: {} cannot keep {} in {}, it gets {} instead",
+            client_name,
+            client.internal_address,
+            path.display(),
+            address
+        );
+        client.set_internal_address(address);
+    }
+
+    if let Some(address_v6) = client.internal_address_v6 {
+        if !destination.is_address_free(address_v6) {
+            let address = destination.next_available_address_v6();
+
+            match address {
+                Some(address) => eprintln!(
+                    "warning: {} cannot keep {} in {}, it gets {} instead",
+                    client_name,
+                    address_v6,
+                    path.display(),
+                    address
+                ),
+                None => eprintln!(
+                    "warning: {} cannot keep {} in {}, which has no free IPv6 address, it is dropped",
+                    client_name,
+                    address_v6,
+                    path.display()
+                ),
+            }
+
+            client.set_internal_address_v6(address);
+        }
+    }
+
+    destination.add_peer(client)?;
+
+    // the destination is saved first, so that a failure never loses the client
+    destination.save()?;
+    config.remove_peer(client_name);
+    config.save()?;
+
+    if !config.writes_to_stdout() {
+        println!(
+            "Client {} moved to {}, regenerate its configuration",
+            client_name,
+            path.display()
+        );
+    }
+
+    Ok(())
+}
+
+fn handle_rotate_router_keys(config: &mut Configuration, yes: bool) -> Result<(), ConfigError> {
+    if !yes {
+        Err(ConfigError::Usage("Rotating the router keys cuts every client off until it gets its new configuration, confirm with --yes"))?;
+    }
+
+    if config.router.private_key_file.is_some() || config.router.public_key_file.is_some() {
+        Err(ConfigError::KeysFromFiles(config.router.name.clone()))?;
+    }
+
+    config.router.rotate_keys().map_err(ConfigError::KeyGen)?;
+    config.save()?;
+
+    if !config.writes_to_stdout() {
+        println!("Keys rotated for router {}", config.router.name);
+        println!("New public key: {}", config.router.public_key);
+    }
+
+    // the clients only know the previous public key
+    if !config.clients.is_empty() {
+        eprintln!("warning: the configuration of every client must be given again:");
+
+        for client in &config.clients {
+            eprintln!("  {}", client.name);
+        }
+
+        eprintln!("Run `export-all <dir>` to write them all");
+    }
+
+    Ok(())
+}
+
+fn handle_set_router(config: &mut Configuration, args: SetRouterArgs) -> Result<(), ConfigError> {
+    let SetRouterArgs {
+        listen_port,
+        endpoint_port,
+        default_keepalive,
+        mtu,
+        jumbo,
+        table,
+        preup,
+        postup,
+        predown,
+        postdown,
+        fwmark,
+        save_config,
+    } = args;
+
+    if let Some(endpoint_port) = endpoint_port {
+        if endpoint_port == 0 {
+            Err(ConfigError::InvalidPort("endpoint"))?;
+        }
+
+        // the router listened on the former endpoint port, which it keeps doing
+        if config.router.listen_port.is_none() {
+            config
+                .router
+                .set_listen_port(Some(config.router.external_address.port));
+        }
+
+        let mut external_address = config.router.external_address.clone();
+
+        external_address.port = endpoint_port;
+        config.router.set_external_address(external_address);
+
+        if !config.clients.is_empty() {
+            eprintln!("warning: client configurations already distributed keep the previous endpoint port, regenerate them");
+        }
+    }
+
+    if let Some(listen_port) = listen_port {
+        if listen_port == 0 {
+            Err(ConfigError::InvalidPort("listen"))?;
+        }
+
+        config.router.set_listen_port(Some(listen_port));
+    }
+
+    if let Some(keepalive) = default_keepalive {
+        // 0 removes the default rather than disabling keepalives of new clients
+        config.default_keepalive = Some(keepalive).filter(|keepalive| *keepalive != 0);
+    }
+
+    if let Some(mtu) = mtu {
+        if let Some(issue) =
+            validation::check_mtu(&config.router.name, mtu, jumbo, config.router.has_ipv6())
+        {
+            Err(ConfigError::InvalidMtu(issue.message))?;
+        }
+
+        config.router.set_mtu(Some(mtu));
+    }
+
+    if let Some(table) = table {
+        config.router.set_table(Some(table));
+    }
+
+    if let Some(preup) = preup {
+        config.router.set_preup(Some(preup));
+    }
+
+    if let Some(postup) = postup {
+        config.router.set_postup(Some(postup));
+    }
+
+    if let Some(predown) = predown {
+        config.router.set_predown(Some(predown));
+    }
+
+    if let Some(postdown) = postdown {
+        config.router.set_postdown(Some(postdown));
+    }
+
+    if let Some(fwmark) = fwmark {
+        config.router.set_fwmark(Some(fwmark));
+    }
+
+    if let Some(save_config) = save_config {
+        config.router.set_save_config(Some(save_config));
+    }
+
+    config.save()?;
+
+    if !config.writes_to_stdout() {
+        println!("Router {} updated", config.router.name);
+    }
+
+    Ok(())
+}
+
+fn handle_set_endpoint(
+    config: &mut Configuration,
+    endpoint: Option<AddrPort>,
+    alternates: Vec<AddrPort>,
+    clear_alternates: bool,
+    verify_endpoint: bool,
+) -> Result<(), Box<dyn Error>> {
+    if verify_endpoint {
+        for endpoint in endpoint.iter().chain(&alternates) {
+            warn_unresolved_endpoint(endpoint);
+        }
+    }
+
+    let endpoint_changed = endpoint.is_some();
+
+    if let Some(endpoint) = endpoint {
+        config.router.set_external_address(endpoint);
+    }
+
+    if clear_alternates {
+        config.router.clear_alternate_endpoints();
+    }
+
+    for alternate in alternates {
+        config.router.push_alternate_endpoint(alternate);
+    }
+
+    config.save()?;
+
+    if !config.writes_to_stdout() {
+        println!(
+            "Router {} endpoint set to {}",
+            config.router.name, config.router.external_address
+        );
+
+        for (index, alternate) in config.router.alternate_endpoints.iter().enumerate() {
+            println!("  alternate endpoint {}: {}", index + 1, alternate);
+        }
+    }
+
+    // alternate endpoints are only used by configurations generated with them
+    if endpoint_changed && !config.clients.is_empty() {
+        eprintln!("warning: client configurations already distributed keep the previous endpoint, regenerate them");
+    }
+
+    Ok(())
+}
+
+/// Warns if a host name endpoint does not resolve, as clients could not reach it
+fn warn_unresolved_endpoint(endpoint: &AddrPort) {
+    if let Some(issue) = validation::check_endpoint_resolves(endpoint) {
+        eprintln!("{}", issue);
+    }
+}
+
+fn handle_router_config(
+    config: &Configuration,
+    tag: Option<&str>,
+    target_version: Option<&WgVersion>,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let router_conf = config.router_conf_string_for(tag)?;
+
+    warn_compatibility(&router_conf, target_version);
+    write_output(&router_conf, output)
+}
+
+/// Writes a rendered configuration to `output` with owner-only permissions, or to stdout
+/// when no output (or `-`) is given
+fn write_output(conf: &str, output: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    match output {
+        Some(path) if path != Path::new("-") => {
+            configuration::write_private_file(path, conf)?;
+            println!("Configuration written to {}", path.display());
+        }
+        _ => print!("{}", conf),
+    }
+
+    Ok(())
+}
+
+/// Runs `wg-quick` on the interface named after the configuration. Bringing the interface up
+/// first writes the router configuration where wg-quick looks for it.
+fn handle_wg_quick(config: &Configuration, actions: &[&str]) -> Result<(), Box<dyn Error>> {
+    let interface = match interface_name(config) {
+        Some(interface) => interface,
+        None => Err(ConfigError::Usage(
+            "The configuration has no name to use as interface.",
+        ))?,
+    };
+
+    if actions.contains(&"up") {
+        let path = Path::new(WG_QUICK_DIR).join(format!("{}.conf", interface));
+        let router_conf = config.router_conf_string()?;
+
+        // wg-quick relies on the local wg, which may be too old for some directives
+        warn_compatibility(&router_conf, keys::wg_version());
+
+        match configuration::write_private_file(&path, &router_conf) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Err(format!(
+                "Cannot write {}, root privileges are required.",
+                path.display()
+            ))?,
+            Err(e) => Err(format!("Cannot write {}: {}", path.display(), e))?,
+        }
+    }
+
+    for action in actions {
+        let status = match Command::new("wg-quick")
+            .args([action, &interface.as_str()])
+            .status()
+        {
+            Ok(status) => status,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err("wireguard-tools (`wg-quick`) not found in PATH.")?
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if !status.success() {
+            Err(format!(
+                "`wg-quick {} {}` failed, root privileges may be required.",
+                action, interface
+            ))?
+        }
+    }
+
+    Ok(())
+}
+
+fn warn_compatibility(conf: &str, target_version: Option<&WgVersion>) {
+    if let Some(target_version) = target_version {
+        for warning in compat::compatibility_warnings(conf, target_version) {
+            eprintln!("warning: {}", warning);
+        }
+    }
+}
+
+/// The live interface is named after the configuration, so configurations read from stdin
+/// cannot be applied
+fn interface_name(config: &Configuration) -> Option<String> {
+    config
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.name.clone())
+}
+
+/// Runs `wg` with the given arguments against the live interface
+fn apply_to_interface(wg_args: &[String]) -> Result<(), String> {
+    let output = match Command::new(keys::wg_binary()).args(wg_args).output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(format!(
+                "wireguard-tools (`{}`) not found",
+                keys::wg_binary().display()
+            ))
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+
+    if !output.status.success() {
+        return Err(format!(
+            "is interface {} up? wg: {}",
+            wg_args[1],
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    println!("Changes applied to interface {}", wg_args[1]);
+
+    Ok(())
+}
+
+fn is_tty() -> bool {
+    atty::is(Stream::Stdin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_configuration_matches_its_snapshot() {
+        for (ipv6, snapshot) in [
+            (
+                false,
+                include_str!("../tests/snapshots/generate-example.yaml"),
+            ),
+            (
+                true,
+                include_str!("../tests/snapshots/generate-example-ipv6.yaml"),
+            ),
+        ] {
+            let example = example_configuration(ipv6).to_yaml(true);
+
+            assert_eq!(example, snapshot);
+            assert_eq!(example_configuration(ipv6).to_yaml(true), example);
+        }
+    }
+
+    /// The client add-client builds from `args` for the example configuration, given its
+    /// public key for no keys to be generated
+    fn example_client(args: &[&str]) -> Result<Peer, Box<dyn Error>> {
+        let args = ["add-client", "client-c", "-i", "10.0.1.9", "--pub"]
+            .iter()
+            .chain(&[EXAMPLE_CLIENT_A_PUBLIC_KEY])
+            .chain(args);
+
+        new_client(
+            &example_configuration(false),
+            AddClientArgs::from_iter(args),
+            None,
+        )
+    }
+
+    #[test]
+    fn like_inherits_the_settings_of_the_referenced_client() {
+        let client = example_client(&["--like", "client-a"]).unwrap();
+
+        assert_eq!(client.allowed_ips, ["0.0.0.0/0".parse::<IpNet>().unwrap()]);
+        assert_eq!(client.dns, ["10.0.1.1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(client.persistent_keepalive, Some(25));
+    }
+
+    #[test]
+    fn explicit_settings_override_the_referenced_client() {
+        let client =
+            example_client(&["--like", "client-a", "-a", "10.0.1.0/24", "-p", "0"]).unwrap();
+
+        assert_eq!(
+            client.allowed_ips,
+            ["10.0.1.0/24".parse::<IpNet>().unwrap()]
+        );
+        assert_eq!(client.dns, ["10.0.1.1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(client.persistent_keepalive, Some(0));
+    }
+
+    #[test]
+    fn like_refuses_an_unknown_client() {
+        let e = example_client(&["--like", "client-z"]).unwrap_err();
+
+        assert!(matches!(
+            e.downcast_ref::<ConfigError>(),
+            Some(ConfigError::ClientNotFound(name)) if name == "client-z"
+        ));
+    }
 }
@@ -0,0 +1,188 @@
+use crate::addrport::AddrPort;
+use crate::endpoint::TableType;
+use std::fmt;
+use std::net::{IpAddr, ToSocketAddrs};
+
+/// Smallest MTU accepted, the minimum datagram size every IPv4 host handles
+pub const MIN_MTU: u16 = 576;
+/// Smallest MTU accepted for interfaces with an IPv6 address, the IPv6 minimum link MTU
+pub const MIN_IPV6_MTU: u16 = 1280;
+/// Largest MTU accepted by default, the usual Ethernet MTU
+pub const MAX_MTU: u16 = 1500;
+/// Largest MTU accepted when jumbo frames are allowed
+pub const MAX_JUMBO_MTU: u16 = 9000;
+/// Largest persistent keepalive accepted, in seconds
+pub const MAX_KEEPALIVE: usize = 3600;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Issue {
+    pub fn warning<S: Into<String>>(message: S) -> Issue {
+        Issue {
+            severity: Severity::Warning,
+            message: message.into(),
+        }
+    }
+
+    pub fn error<S: Into<String>>(message: S) -> Issue {
+        Issue {
+            severity: Severity::Error,
+            message: message.into(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+/// Checks that `mtu` is within `MIN_MTU..=MAX_MTU`, or up to `MAX_JUMBO_MTU` if `jumbo`. The
+/// minimum is `MIN_IPV6_MTU` if `ipv6`, as IPv6 is disabled on links with a smaller MTU.
+pub fn check_mtu(owner: &str, mtu: u16, jumbo: bool, ipv6: bool) -> Option<Issue> {
+    let min = if ipv6 { MIN_IPV6_MTU } else { MIN_MTU };
+    let max = if jumbo { MAX_JUMBO_MTU } else { MAX_MTU };
+
+    if (min..=max).contains(&mtu) {
+        return None;
+    }
+
+    let hint = if mtu < min && ipv6 {
+        ", the minimum for IPv6"
+    } else if mtu > max && !jumbo {
+        ", use --jumbo for jumbo frames"
+    } else {
+        ""
+    };
+
+    Some(Issue::error(format!(
+        "{}'s MTU {} is outside of {}..={}{}",
+        owner, mtu, min, max, hint
+    )))
+}
+
+/// Checks that `keepalive` is at most `MAX_KEEPALIVE`, 0 being valid as it disables it
+pub fn check_keepalive(owner: &str, keepalive: usize) -> Option<Issue> {
+    if keepalive <= MAX_KEEPALIVE {
+        return None;
+    }
+
+    Some(Issue::error(format!(
+        "{}'s persistent keepalive {} is above {} seconds, 25 is the usual value",
+        owner, keepalive, MAX_KEEPALIVE
+    )))
+}
+
+/// Checks that a custom routing table is one routes can be added to: not 0, which the kernel
+/// reads as unspecified, nor the reserved `default` (253) and `local` (255) tables
+pub fn check_table(owner: &str, table: &TableType) -> Option<Issue> {
+    match table {
+        TableType::Custom(0) => Some(Issue::error(format!(
+            "{}'s routing table 0 is not a table, use off to add no routes",
+            owner
+        ))),
+        TableType::Custom(number @ (253 | 255)) => Some(Issue::warning(format!(
+            "{}'s routing table {} is reserved by the kernel",
+            owner, number
+        ))),
+        _ => None,
+    }
+}
+
+/// Checks that a host name endpoint resolves to at least one address, IP literals needing no
+/// resolution
+pub fn check_endpoint_resolves(endpoint: &AddrPort) -> Option<Issue> {
+    if endpoint.address.parse::<IpAddr>().is_ok() {
+        return None;
+    }
+
+    match (endpoint.address.as_str(), endpoint.port).to_socket_addrs() {
+        Ok(addresses) if addresses.len() > 0 => None,
+        Ok(_) => Some(Issue::warning(format!(
+            "endpoint {} does not resolve to any address",
+            endpoint
+        ))),
+        Err(e) => Some(Issue::warning(format!(
+            "endpoint {} does not resolve: {}",
+            endpoint, e
+        ))),
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_holding_routes_pass() {
+        for table in [
+            TableType::Off,
+            TableType::Auto,
+            TableType::Custom(1),
+            TableType::Custom(254),
+            TableType::Custom(51820),
+        ] {
+            assert!(check_table("client", &table).is_none(), "{}", table);
+        }
+    }
+
+    #[test]
+    fn table_zero_is_an_error_and_reserved_tables_a_warning() {
+        let zero = check_table("client", &TableType::Custom(0)).unwrap();
+        assert!(zero.is_error());
+
+        for number in [253, 255] {
+            let reserved = check_table("client", &TableType::Custom(number)).unwrap();
+
+            assert_eq!(reserved.severity, Severity::Warning);
+            assert!(reserved.message.contains(&number.to_string()));
+        }
+    }
+
+    #[test]
+    fn mtus_below_1280_are_refused_with_ipv6() {
+        assert!(check_mtu("vpn", 576, false, false).is_none());
+        assert!(check_mtu("vpn", 1280, false, true).is_none());
+        assert!(check_mtu("vpn", 9000, true, true).is_none());
+
+        let issue = check_mtu("vpn", 1000, false, true).unwrap();
+        assert!(issue.is_error());
+        assert_eq!(
+            issue.message,
+            "vpn's MTU 1000 is outside of 1280..=1500, the minimum for IPv6"
+        );
+
+        let issue = check_mtu("vpn", 575, false, false).unwrap();
+        assert_eq!(issue.message, "vpn's MTU 575 is outside of 576..=1500");
+
+        let issue = check_mtu("vpn", 1600, false, true).unwrap();
+        assert_eq!(
+            issue.message,
+            "vpn's MTU 1600 is outside of 1280..=1500, use --jumbo for jumbo frames"
+        );
+    }
+}
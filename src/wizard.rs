@@ -0,0 +1,74 @@
+use crate::args::AddClientArgs;
+use dialoguer::Input;
+use ipnet::IpNet;
+use std::error::Error;
+use std::net::IpAddr;
+use std::str::FromStr;
+use wireguard_configure::Configuration;
+
+/// Parses a comma-separated list, an empty string being an empty list
+fn parse_list<T: FromStr>(s: &str) -> Result<Vec<T>, T::Err> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// Prompts for the settings of a new client which were not given on the command line
+pub fn complete_add_client(
+    config: &Configuration,
+    args: &mut AddClientArgs,
+) -> Result<(), Box<dyn Error>> {
+    if args.internal_address.is_none() {
+        let mut input = Input::<IpAddr>::new().with_prompt("Internal address");
+
+        if let Some(address) = config.next_available_address() {
+            input = input.default(address);
+        }
+
+        args.internal_address = Some(input.interact_text()?);
+    }
+
+    let tunnel_given = args.like.is_some() || args.full_tunnel || args.split_tunnel;
+
+    if args.allowed_ips.is_empty() && !tunnel_given {
+        let allowed_ips: String = Input::new()
+            .with_prompt("Allowed IPs, comma-separated")
+            .default(
+                config
+                    .router
+                    .subnets()
+                    .iter()
+                    .map(|subnet| subnet.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            )
+            .validate_with(|s: &String| parse_list::<IpNet>(s).map(|_| ()))
+            .interact_text()?;
+
+        args.allowed_ips = parse_list(&allowed_ips)?;
+    }
+
+    if args.dns.is_empty() {
+        let dns: String = Input::new()
+            .with_prompt("DNS servers, comma-separated")
+            .allow_empty(true)
+            .validate_with(|s: &String| parse_list::<IpAddr>(s).map(|_| ()))
+            .interact_text()?;
+
+        args.dns = parse_list(&dns)?;
+    }
+
+    if args.persistent_keepalive.is_none() {
+        let keepalive: String = Input::new()
+            .with_prompt("Persistent keepalive in seconds, empty for none")
+            .allow_empty(true)
+            .validate_with(|s: &String| parse_list::<usize>(s).map(|_| ()))
+            .interact_text()?;
+
+        args.persistent_keepalive = parse_list(&keepalive)?.first().copied();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,529 @@
+//! Runs the wireguard-configure binary on configurations built with the library, the commands
+//! tested needing no `wg` but a stand-in answering `check`
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use wireguard_configure::configuration::ConfigFormat;
+use wireguard_configure::{AddrPort, Configuration, Peer, Router};
+
+const ROUTER_PRIVATE_KEY: &str = "cm91dGVyLXByaXZhdGUta2V5LTAwMDAwMDAwMDAwMDA=";
+const ROUTER_PUBLIC_KEY: &str = "cm91dGVyLXB1YmxpYy1rZXktMDAwMDAwMDAwMDAwMDA=";
+const CLIENT_PRIVATE_KEY: &str = "Y2xpZW50LWEtcHJpdmF0ZS1rZXktMDAwMDAwMDAwMDA=";
+const CLIENT_PUBLIC_KEY: &str = "Y2xpZW50LWEtcHVibGljLWtleS0wMDAwMDAwMDAwMDA=";
+const NEW_CLIENT_PUBLIC_KEY: &str = "bmV3LWNsaWVudC1wdWJsaWMta2V5LTAwMDAwMDAwMDA=";
+
+/// A router at 10.0.1.1/24 reached at vpn.example.com:51820, and client-a at 10.0.1.2
+fn sample() -> Configuration {
+    let router = Router::from_keys(
+        "vpn",
+        "10.0.1.1/24".parse().unwrap(),
+        AddrPort::new("vpn.example.com", 51820),
+        ROUTER_PRIVATE_KEY.to_string(),
+        ROUTER_PUBLIC_KEY.to_string(),
+    );
+    let mut config = Configuration::new(router);
+
+    config.push_peer(
+        Peer::from_keys(
+            "client-a",
+            "10.0.1.2".parse().unwrap(),
+            Some(CLIENT_PRIVATE_KEY.to_string()),
+            CLIENT_PUBLIC_KEY.to_string(),
+        )
+        .with_allowed_ips("10.0.1.0/24".parse().unwrap()),
+    );
+
+    config
+}
+
+/// Runs the binary with `args`, piping `stdin` to it
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wireguard-configure"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+/// Runs the binary on the configuration file at `path`
+fn run_on(path: &Path, args: &[&str]) -> Output {
+    let path = path.to_str().unwrap();
+    let args: Vec<&str> = ["-c", path].iter().chain(args).copied().collect();
+
+    run(&args, "")
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+/// Writes `config` as YAML to a private file of a new directory
+fn write_config(config: &Configuration) -> (tempfile::TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("wg0.yaml");
+
+    wireguard_configure::configuration::write_private_file(&path, &config.to_yaml(true)).unwrap();
+    (dir, path)
+}
+
+#[test]
+fn piped_toml_and_yaml_are_both_read() {
+    for format in [ConfigFormat::Toml, ConfigFormat::Yaml] {
+        let output = run(
+            &["list", "--output-format", "json"],
+            &sample().to_format(format, true),
+        );
+        let list: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+
+        assert_eq!(list["router"]["name"], "vpn", "{:?}", format);
+        assert_eq!(list["clients"][0]["name"], "client-a", "{:?}", format);
+    }
+}
+
+#[test]
+fn piped_configurations_are_printed_back_in_their_format() {
+    let toml = sample().to_format(ConfigFormat::Toml, true);
+
+    // whether the format is given or detected
+    for args in [
+        &["--format", "toml", "set-router", "--mtu", "1420"][..],
+        &["set-router", "--mtu", "1420"],
+    ] {
+        let output = run(args, &toml);
+        let changed = Configuration::from_str_as(&stdout(&output), ConfigFormat::Toml).unwrap();
+
+        assert_eq!(changed.router.mtu, Some(1420), "{:?}", args);
+    }
+}
+
+#[test]
+fn configuration_files_are_saved_in_the_format_given() {
+    let (_dir, path) = write_config(&sample());
+    std::fs::write(&path, sample().to_format(ConfigFormat::Toml, true)).unwrap();
+
+    stdout(&run_on(
+        &path,
+        &["set-router", "--mtu", "1420", "--format", "toml"],
+    ));
+    let text = std::fs::read_to_string(&path).unwrap();
+    let changed = Configuration::from_str_as(&text, ConfigFormat::Toml).unwrap();
+
+    assert_eq!(changed.router.mtu, Some(1420));
+}
+
+/// Parses a configuration printed with its private keys
+fn parse_yaml(text: &str) -> Configuration {
+    Configuration::from_str_as(text, ConfigFormat::Yaml).unwrap()
+}
+
+#[test]
+fn dry_run_add_prints_the_change_and_leaves_the_file() {
+    let (_dir, path) = write_config(&sample());
+    let before = std::fs::read_to_string(&path).unwrap();
+
+    let output = run_on(
+        &path,
+        &[
+            "--dry-run",
+            "--reveal-secrets",
+            "add-client",
+            "client-b",
+            "-i",
+            "10.0.1.3",
+            "-a",
+            "10.0.1.0/24",
+            "--pub",
+            NEW_CLIENT_PUBLIC_KEY,
+        ],
+    );
+    let printed = parse_yaml(&stdout(&output));
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), before);
+    assert_eq!(printed.clients.len(), 2);
+    assert_eq!(printed.clients[1].name, "client-b");
+    assert_eq!(
+        printed.clients[0].private_key.as_deref(),
+        Some(CLIENT_PRIVATE_KEY)
+    );
+}
+
+#[test]
+fn dry_run_remove_prints_the_change_and_leaves_the_file() {
+    let (_dir, path) = write_config(&sample());
+    let before = std::fs::read_to_string(&path).unwrap();
+
+    let output = run_on(&path, &["--dry-run", "remove-client", "client-a"]);
+    let printed = stdout(&output);
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), before);
+    assert!(!printed.contains("client-a"));
+    assert!(printed.contains("name: vpn"));
+    // a preview redacts the private keys
+    assert!(printed.contains("<redacted>"));
+    assert!(!printed.contains(ROUTER_PRIVATE_KEY));
+}
+
+#[test]
+fn dry_run_cannot_apply() {
+    let (_dir, path) = write_config(&sample());
+    let output = run_on(
+        &path,
+        &["--dry-run", "remove-client", "client-a", "--apply"],
+    );
+
+    assert!(!output.status.success());
+}
+
+#[test]
+fn each_table_given_to_add_client_is_rendered_in_the_interface() {
+    for (table, rendered) in [("off", "off"), ("AUTO", "auto"), ("1234", "1234")] {
+        let (_dir, path) = write_config(&sample());
+        let output = run_on(
+            &path,
+            &[
+                "add-client",
+                "client-b",
+                "-i",
+                "10.0.1.3",
+                "-a",
+                "0.0.0.0/0",
+                "--pub",
+                NEW_CLIENT_PUBLIC_KEY,
+                "--table",
+                table,
+            ],
+        );
+        stdout(&output);
+
+        // giving the new client a private key, for its configuration to be rendered
+        let mut config = Configuration::from_path(&path).unwrap();
+        config.clients[1].set_private_key(Some(CLIENT_PRIVATE_KEY.to_string()));
+        wireguard_configure::configuration::write_private_file(&path, &config.to_yaml(true))
+            .unwrap();
+
+        let conf = stdout(&run_on(&path, &["client-config", "client-b"]));
+        let (interface, peer) = conf.split_once("[Peer]").unwrap();
+
+        assert!(
+            interface.contains(&format!("Table = {}\n", rendered)),
+            "{}",
+            conf
+        );
+        assert!(!peer.contains("Table"), "{}", conf);
+    }
+}
+
+#[test]
+fn invalid_tables_are_refused_by_add_client() {
+    for table in ["0", "main"] {
+        let (_dir, path) = write_config(&sample());
+        let before = std::fs::read_to_string(&path).unwrap();
+        let output = run_on(
+            &path,
+            &[
+                "add-client",
+                "client-b",
+                "-i",
+                "10.0.1.3",
+                "-a",
+                "0.0.0.0/0",
+                "--pub",
+                NEW_CLIENT_PUBLIC_KEY,
+                "--table",
+                table,
+            ],
+        );
+
+        assert_eq!(output.status.code(), Some(2), "{}", table);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), before);
+    }
+}
+
+#[test]
+fn router_listens_on_one_port_and_advertises_another() {
+    let (_dir, path) = write_config(&sample());
+
+    stdout(&run_on(&path, &["set-router", "--endpoint-port", "443"]));
+
+    let router_conf = stdout(&run_on(&path, &["router-config"]));
+    let client_conf = stdout(&run_on(&path, &["client-config", "client-a"]));
+
+    assert!(
+        router_conf.contains("ListenPort = 51820\n"),
+        "{}",
+        router_conf
+    );
+    assert!(
+        client_conf.contains("Endpoint = vpn.example.com:443\n"),
+        "{}",
+        client_conf
+    );
+}
+
+/// Writes a stand-in for `wg` reporting a version and deriving the public keys of `sample()`
+#[cfg(unix)]
+fn write_fake_wg(dir: &Path) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.join("wg");
+    let script = format!(
+        "#!/bin/sh
+case \"$1\" in
+--version) echo 'wireguard-tools v1.0.20210914 - https://git.zx2c4.com/wireguard-tools/' ;;
+pubkey)
+    read key
+    case \"$key\" in
+    {}) echo {} ;;
+    {}) echo {} ;;
+    *) echo 'Key is not the correct length or format' >&2; exit 1 ;;
+    esac ;;
+*) exit 1 ;;
+esac
+",
+        ROUTER_PRIVATE_KEY, ROUTER_PUBLIC_KEY, CLIENT_PRIVATE_KEY, CLIENT_PUBLIC_KEY
+    );
+
+    std::fs::write(&path, script).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+/// `sample()` reached at an IP literal, for its endpoint to need no resolution
+#[cfg(unix)]
+fn healthy_sample() -> Configuration {
+    let mut config = sample();
+    config.router.external_address = AddrPort::new("203.0.113.1", 51820);
+    config
+}
+
+#[cfg(unix)]
+#[test]
+fn check_passes_a_healthy_configuration() {
+    let (dir, path) = write_config(&healthy_sample());
+    let wg = write_fake_wg(dir.path());
+
+    let output = run_on(&path, &["--wg-path", wg.to_str().unwrap(), "check"]);
+    let report = stdout(&output);
+
+    for section in ["configuration", "endpoint", "environment", "keys"] {
+        assert!(
+            report.contains(&format!("[PASS] {}\n", section)),
+            "{}",
+            report
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn check_fails_on_a_validation_error() {
+    let mut config = healthy_sample();
+    let mut twin = config.clients[0].clone();
+    twin.name = "client-b".to_string();
+    config.push_peer(twin);
+
+    let (dir, path) = write_config(&config);
+    let wg = write_fake_wg(dir.path());
+
+    let output = run_on(&path, &["--wg-path", wg.to_str().unwrap(), "check"]);
+    let report = String::from_utf8(output.stdout).unwrap();
+
+    assert_eq!(output.status.code(), Some(4), "{}", report);
+    assert!(report.contains("[FAIL] configuration: "), "{}", report);
+}
+
+#[test]
+fn invalid_changes_exit_with_the_invalid_code() {
+    for args in [
+        &["set-router", "--listen-port", "0"][..],
+        &["set-router", "--endpoint-port", "0"],
+        &["set-router", "--mtu", "100"],
+        &["reserve", "10.0.1.128/28", "--remove"],
+        &["client-config", "client-a", "--endpoint-index", "1"],
+    ] {
+        let (_dir, path) = write_config(&sample());
+        let before = std::fs::read_to_string(&path).unwrap();
+        let output = run_on(&path, args);
+
+        assert_eq!(output.status.code(), Some(4), "{:?}", args);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), before);
+    }
+}
+
+#[test]
+fn unconfirmed_router_key_rotation_is_a_usage_error() {
+    let (_dir, path) = write_config(&sample());
+    let output = run_on(&path, &["rotate-router-keys"]);
+
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn alternate_endpoints_are_picked_by_index() {
+    let (_dir, path) = write_config(&sample());
+
+    stdout(&run_on(
+        &path,
+        &[
+            "set-endpoint",
+            "--alternate",
+            "vpn2.example.com:443",
+            "--alternate",
+            "[fd00::1]:51820",
+        ],
+    ));
+
+    for (index, endpoint) in [
+        ("0", "vpn.example.com:51820"),
+        ("1", "vpn2.example.com:443"),
+        ("2", "[fd00::1]:51820"),
+    ] {
+        let conf = stdout(&run_on(
+            &path,
+            &["client-config", "client-a", "--endpoint-index", index],
+        ));
+
+        assert!(
+            conf.contains(&format!("Endpoint = {}\n", endpoint)),
+            "{}",
+            conf
+        );
+    }
+
+    stdout(&run_on(
+        &path,
+        &[
+            "set-endpoint",
+            "vpn.example.org:51820",
+            "--clear-alternates",
+        ],
+    ));
+    let config = Configuration::from_path(&path).unwrap();
+
+    assert_eq!(config.router.endpoints().len(), 1);
+    assert_eq!(config.router.external_address.address, "vpn.example.org");
+}
+
+#[test]
+fn apply_without_an_interface_name_is_refused() {
+    for args in [
+        &[
+            "add-client",
+            "client-b",
+            "-i",
+            "10.0.1.3",
+            "-a",
+            "10.0.1.0/24",
+            "--pub",
+            NEW_CLIENT_PUBLIC_KEY,
+            "--apply",
+        ][..],
+        &[
+            "add-client",
+            "--count",
+            "2",
+            "--name-prefix",
+            "batch-",
+            "-a",
+            "10.0.1.0/24",
+            "--apply",
+        ],
+        &["remove-client", "client-a", "--apply"],
+    ] {
+        // a piped configuration has no name to use as interface, and is printed back once changed
+        let output = run(args, &sample().to_yaml(true));
+
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(output.stdout.is_empty(), "{:?}", args);
+    }
+}
+
+#[test]
+fn keys_not_managed_by_the_configuration_are_not_rotated() {
+    let mut config = sample();
+    config.push_peer(
+        Peer::from_keys(
+            "client-b",
+            "10.0.1.3".parse().unwrap(),
+            None,
+            NEW_CLIENT_PUBLIC_KEY.to_string(),
+        )
+        .with_allowed_ips("10.0.1.0/24".parse().unwrap()),
+    );
+
+    let (dir, path) = write_config(&config);
+    let key_file = dir.path().join("client-a.key");
+    std::fs::write(&key_file, CLIENT_PRIVATE_KEY).unwrap();
+
+    let mut config = Configuration::from_path(&path).unwrap();
+    config.clients[0].set_private_key(None);
+    config.clients[0].set_private_key_file(Some(key_file));
+    wireguard_configure::configuration::write_private_file(&path, &config.to_yaml(true)).unwrap();
+    let before = std::fs::read_to_string(&path).unwrap();
+
+    for client in ["client-a", "client-b"] {
+        let output = run_on(&path, &["rotate-keys", client]);
+
+        assert_eq!(output.status.code(), Some(4), "{}", client);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), before);
+    }
+}
+
+#[test]
+fn changes_not_applied_are_saved_but_fail() {
+    let (dir, path) = write_config(&sample());
+    let missing_wg = dir.path().join("no-wg");
+    let missing_wg = missing_wg.to_str().unwrap();
+
+    let output = run_on(
+        &path,
+        &[
+            "--wg-path",
+            missing_wg,
+            "add-client",
+            "client-b",
+            "-i",
+            "10.0.1.3",
+            "-a",
+            "10.0.1.0/24",
+            "--pub",
+            NEW_CLIENT_PUBLIC_KEY,
+            "--apply",
+        ],
+    );
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(Configuration::from_path(&path).unwrap().clients.len(), 2);
+
+    let output = run_on(
+        &path,
+        &[
+            "--wg-path",
+            missing_wg,
+            "remove-client",
+            "client-b",
+            "--apply",
+        ],
+    );
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(Configuration::from_path(&path).unwrap().clients.len(), 1);
+}